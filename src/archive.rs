@@ -0,0 +1,114 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use bzip2::read::BzDecoder;
+use xz2::read::XzDecoder;
+use crate::utils;
+
+pub fn download(url: &str, dest: &Path) -> io::Result<()> {
+    let mut resp = utils::http_client()
+        .get(url)
+        .send()
+        .map_err(io::Error::other)?;
+    if !resp.status().is_success() {
+        return Err(io::Error::other(format!("Download of {} failed: {}", url, resp.status())));
+    }
+    let mut file = fs::File::create(dest)?;
+    resp.copy_to(&mut file).map_err(io::Error::other)?;
+    Ok(())
+}
+
+fn decoder(archive: &Path) -> io::Result<Box<dyn Read>> {
+    let mut file = fs::File::open(archive)?;
+    let mut magic = [0u8; 6];
+    let read = file.read(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+    let magic = &magic[..read];
+    let name = archive.to_string_lossy();
+    if magic.starts_with(&[0x1f, 0x8b]) || name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Ok(Box::new(GzDecoder::new(file)))
+    } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) || name.ends_with(".tar.xz") || name.ends_with(".txz") {
+        Ok(Box::new(XzDecoder::new(file)))
+    } else if magic.starts_with(b"BZh") || name.ends_with(".tar.bz2") || name.ends_with(".tbz2") {
+        Ok(Box::new(BzDecoder::new(file)))
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unsupported archive format: {}", archive.display())))
+    }
+}
+
+pub fn extract(archive: &Path, dest: &Path, strip_components: Option<usize>) -> io::Result<()> {
+    let strip = match strip_components {
+        Some(n) => n,
+        None => usize::from(has_single_top_dir(archive)?),
+    };
+    let mut tar = tar::Archive::new(decoder(archive)?);
+    fs::create_dir_all(dest)?;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_pax_global_extensions() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let stripped: PathBuf = path.components().filter(|c| !matches!(c, Component::CurDir)).skip(strip).collect();
+        if stripped.as_os_str().is_empty() || stripped.components().any(|c| !matches!(c, Component::Normal(_))) {
+            continue;
+        }
+        let out = dest.join(&stripped);
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&out)?;
+    }
+    Ok(())
+}
+
+fn has_single_top_dir(archive: &Path) -> io::Result<bool> {
+    let mut tar = tar::Archive::new(decoder(archive)?);
+    let mut top = None;
+    for entry in tar.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_pax_global_extensions() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let mut components = path.components().filter(|c| !matches!(c, Component::CurDir));
+        let Some(first) = components.next() else { continue };
+        let is_dir = components.next().is_some() || entry.header().entry_type().is_dir();
+        if !is_dir || top.as_ref().is_some_and(|t| *t != first.as_os_str().to_os_string()) {
+            return Ok(false);
+        }
+        top = Some(first.as_os_str().to_os_string());
+    }
+    Ok(top.is_some())
+}
+
+pub fn create(dest: &Path, entries: &[(&str, &[u8])]) -> io::Result<()> {
+    let file = fs::File::create(dest)?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    for (name, data) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(chrono::Local::now().timestamp() as u64);
+        header.set_cksum();
+        tar.append_data(&mut header, name, *data)?;
+    }
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+pub fn read_entries(archive: &Path) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut tar = tar::Archive::new(decoder(archive)?);
+    let mut entries = Vec::new();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.push((name, data));
+    }
+    Ok(entries)
+}
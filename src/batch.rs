@@ -0,0 +1,36 @@
+use std::io;
+use ansi_term::Colour::Red;
+use crate::color;
+
+pub struct Batch {
+    keep_going: bool,
+    failures: Vec<(String, String)>,
+}
+
+impl Batch {
+    pub fn new(keep_going: bool) -> Self {
+        Batch { keep_going, failures: Vec::new() }
+    }
+
+    pub fn record(&mut self, name: &str, result: io::Result<()>) -> io::Result<()> {
+        match result {
+            Err(e) if self.keep_going => {
+                eprintln!("{}: {}: {}", color::paint(Red, "Error"), name, e);
+                self.failures.push((name.to_string(), e.to_string()));
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        if self.failures.is_empty() {
+            return Ok(());
+        }
+        eprintln!("{}", color::paint(Red, format!("~> {} package(s) failed:", self.failures.len())));
+        for (name, error) in &self.failures {
+            eprintln!("  {}: {}", name, error.lines().next().unwrap_or_default());
+        }
+        Err(io::Error::other(format!("{} package(s) failed", self.failures.len())))
+    }
+}
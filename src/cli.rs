@@ -1,35 +1,273 @@
+use std::path::PathBuf;
 use clap::{Parser, Subcommand};
+use charoite::install::Jobs;
+use charoite::sandbox::Sandbox;
+use charoite::search::Format;
+use charoite::Source;
 
 #[derive(Parser)]
 #[clap(name = "charoite", version = "0.1.0", author = "")]
 pub struct Cli {
+    #[clap(long, global = true)]
+    pub no_color: bool,
+    #[clap(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    #[clap(long, global = true, value_name = "FILE")]
+    pub registry: Option<PathBuf>,
+    #[clap(long, global = true, value_name = "DIR", conflicts_with = "registry")]
+    pub registry_dir: Option<PathBuf>,
     #[clap(subcommand)]
     pub command: Command,
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 pub enum Command {
     Install {
-        repo: String,
-        #[clap(short, long)]
+        #[clap(required_unless_present_any = ["print_install_path", "path", "bundle"], conflicts_with_all = ["path", "bundle"])]
+        repo: Option<String>,
+        #[clap(long, value_name = "DIR", conflicts_with_all = ["source", "tarball", "commit", "mirror", "bundle"])]
+        path: Option<String>,
+        #[clap(long, conflicts_with_all = ["path", "bundle", "tarball", "branch", "commit"])]
+        script: bool,
+        #[clap(long, value_name = "FILE", conflicts_with_all = ["source", "tarball", "mirror"])]
+        bundle: Option<String>,
+        #[clap(long, requires = "path")]
+        allow_dirty: bool,
+        #[clap(short, long, conflicts_with = "prefix")]
         local: bool,
+        #[clap(long, value_name = "DIR")]
+        prefix: Option<String>,
+        #[clap(long, value_name = "DIR", conflicts_with_all = ["local", "prefix", "stow", "check_only", "only_deps"])]
+        output_dir: Option<PathBuf>,
         #[clap(long)]
+        print_install_path: bool,
+        #[clap(long, value_name = "NEWNAME", conflicts_with = "stow")]
+        rename: Option<String>,
+        #[clap(long, value_name = "HOST", conflicts_with_all = ["gitlab", "codeberg"])]
+        source: Option<Source>,
+        #[clap(long, hide = true, conflicts_with = "codeberg")]
         gitlab: bool,
-        #[clap(long)]
+        #[clap(long, hide = true)]
         codeberg: bool,
         #[clap(short, long)]
         branch: Option<String>,
+        #[clap(long, conflicts_with = "tarball")]
+        commit: Option<String>,
+        #[clap(long, value_name = "URL_TEMPLATE", conflicts_with = "tarball")]
+        mirror: Option<String>,
+        #[clap(long, value_name = "N", conflicts_with = "tarball")]
+        depth: Option<u32>,
+        #[clap(long, conflicts_with_all = ["tarball", "path", "bundle"])]
+        blobless: bool,
         #[clap(short, long)]
         patches: Option<String>,
         #[clap(short, long, num_args = 1..)]
         flags: Vec<String>,
+        #[clap(long, value_name = "NAME")]
+        build_target: Option<String>,
+        #[clap(long, value_name = "NAME")]
+        install_target: Option<String>,
+        #[clap(long)]
+        strip: bool,
+        #[clap(long, value_name = "DIR", conflicts_with = "no_docs")]
+        docdir: Option<PathBuf>,
+        #[clap(long)]
+        no_docs: bool,
+        #[clap(long, value_name = "DIR", conflicts_with = "stow")]
+        bindir: Option<PathBuf>,
+        #[clap(long, value_name = "DIR")]
+        libdir: Option<PathBuf>,
+        #[clap(long, value_name = "DIR")]
+        includedir: Option<PathBuf>,
+        #[clap(long)]
+        no_default_flags: bool,
+        #[clap(long)]
+        user_flags_first: bool,
+        #[clap(long)]
+        porcelain: bool,
         #[clap(short, long)]
         yes: bool,
+        #[clap(long)]
+        resume: bool,
+        #[clap(long)]
+        show_log: bool,
+        #[clap(long)]
+        stow: bool,
+        #[clap(long)]
+        force: bool,
+        #[clap(long, conflicts_with = "cmake_preset")]
+        cmake_generator: Option<String>,
+        #[clap(long, value_name = "NAME", num_args = 0..=1)]
+        cmake_preset: Option<Option<String>>,
+        #[clap(long)]
+        tarball: bool,
+        #[clap(long)]
+        exclude: Vec<String>,
+        #[clap(long)]
+        quiet_git: bool,
+        #[clap(long)]
+        build_cmd: Option<String>,
+        #[clap(long, requires = "build_cmd")]
+        install_cmd: Option<String>,
+        #[clap(long)]
+        check_only: bool,
+        #[clap(long, conflicts_with = "check_only")]
+        only_deps: bool,
+        #[clap(long)]
+        extras: bool,
+        #[clap(long)]
+        no_verify_pkg_config: bool,
+        #[clap(short, long)]
+        jobs: Option<Jobs>,
+        #[clap(long, allow_hyphen_values = true)]
+        rustflags: Option<String>,
+        #[clap(long, value_name = "N")]
+        codegen_units: Option<usize>,
+        #[clap(long, value_name = "TRIPLE", requires = "prefix")]
+        target: Option<String>,
+        #[clap(long, value_name = "FILE", requires = "prefix")]
+        cmake_toolchain: Option<PathBuf>,
+        #[clap(long)]
+        out_of_tree: bool,
+        #[clap(long)]
+        verify_deps_versions: bool,
+        #[clap(long, value_name = "ENGINE")]
+        sandbox: Option<Sandbox>,
+        #[clap(long, value_name = "IMAGE", requires = "sandbox")]
+        sandbox_image: Option<String>,
+        #[clap(long)]
+        allow_downgrade: bool,
+        #[clap(long)]
+        edit_flags: bool,
+        #[clap(long)]
+        review: bool,
+        #[clap(long, value_delimiter = ',', value_name = "SYSTEMS")]
+        prefer: Vec<String>,
+        #[clap(long, value_name = "FILE", conflicts_with_all = ["build_cmd", "prefer"])]
+        build_file: Option<String>,
+        #[clap(long, value_name = "N", requires = "tarball")]
+        strip_components: Option<usize>,
+        #[clap(long = "patch-from", value_name = "REF", conflicts_with = "tarball")]
+        patch_from: Vec<String>,
+        #[clap(long = "patch-url", value_name = "URL")]
+        patch_urls: Vec<String>,
+        #[clap(long)]
+        select_binary: bool,
+        #[clap(long)]
+        group: Option<String>,
+        #[clap(long)]
+        recurse_submodules: bool,
+        #[clap(long, value_name = "OCTAL")]
+        mode: Option<String>,
+        #[clap(long, value_name = "USER:GROUP")]
+        owner: Option<String>,
+        #[clap(long)]
+        offline: bool,
     },
     Search {
         query: String,
+        #[clap(short, long, conflicts_with = "format")]
+        interactive: bool,
+        #[clap(long, default_value = "table")]
+        format: Format,
+        #[clap(long, value_name = "N")]
+        min_stars: Option<u64>,
+        #[clap(long)]
+        no_forks: bool,
+        #[clap(long, alias = "no-cache")]
+        refresh: bool,
+        #[clap(short, long)]
+        yes: bool,
+    },
+    List {
+        #[clap(long, conflicts_with = "metrics")]
+        check: bool,
+        #[clap(long)]
+        metrics: bool,
+        #[clap(long, default_value_t = 4)]
+        parallel: usize,
+        #[clap(long)]
+        group: Option<String>,
     },
     Remove {
+        #[clap(required_unless_present = "group", conflicts_with = "group")]
+        name: Option<String>,
+        #[clap(long)]
+        group: Option<String>,
+        #[clap(long)]
+        keep_libs: bool,
+        #[clap(long, requires = "group")]
+        keep_going: bool,
+        #[clap(short, long, conflicts_with = "group")]
+        local: bool,
+        #[clap(long)]
+        porcelain: bool,
+    },
+    Doctor,
+    Update {
+        #[clap(required_unless_present = "all", conflicts_with = "all")]
+        name: Option<String>,
+        #[clap(long)]
+        all: bool,
+        #[clap(long, default_value_t = 4)]
+        parallel: usize,
+        #[clap(short, long)]
+        yes: bool,
+        #[clap(long)]
+        prefetch: bool,
+        #[clap(long, alias = "changelog")]
+        show_commits: bool,
+        #[clap(long)]
+        keep_going: bool,
+        #[clap(short, long, conflicts_with = "all")]
+        local: bool,
+        #[clap(long)]
+        porcelain: bool,
+    },
+    Pin {
+        name: String,
+        #[clap(short, long)]
+        local: bool,
+    },
+    Unpin {
+        name: String,
+        #[clap(short, long)]
+        local: bool,
+    },
+    SelfUpdate {
+        #[clap(short, long)]
+        yes: bool,
+    },
+    Verify {
         name: String,
+        #[clap(short, long)]
+        local: bool,
+    },
+    Outdated {
+        #[clap(long, default_value_t = 4)]
+        parallel: usize,
+    },
+    History {
+        #[clap(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        #[clap(long)]
+        since: Option<String>,
+        #[clap(long)]
+        package: Option<String>,
+    },
+    Export {
+        file: String,
+    },
+    Import {
+        file: String,
+        #[clap(long)]
+        rebuild: bool,
+        #[clap(short, long)]
+        yes: bool,
+        #[clap(long, value_name = "PARALLEL", num_args = 0..=1, default_missing_value = "4", requires = "rebuild")]
+        prefetch: Option<usize>,
+        #[clap(long, requires = "rebuild")]
+        keep_going: bool,
     },
 }
@@ -0,0 +1,36 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicU8, Ordering};
+use ansi_term::Style;
+
+const AUTO: u8 = 0;
+const ON: u8 = 1;
+const OFF: u8 = 2;
+
+static STATE: AtomicU8 = AtomicU8::new(AUTO);
+
+pub fn init(no_color: bool) {
+    let enabled = !no_color
+        && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        && io::stdout().is_terminal();
+    STATE.store(if enabled { ON } else { OFF }, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    if STATE.load(Ordering::Relaxed) == AUTO {
+        init(false);
+    }
+    STATE.load(Ordering::Relaxed) == ON
+}
+
+pub fn paint(style: impl Into<Style>, text: impl AsRef<str>) -> String {
+    if enabled() {
+        style.into().paint(text.as_ref()).to_string()
+    } else {
+        text.as_ref().to_string()
+    }
+}
+
+pub fn bold(text: impl AsRef<str>) -> String {
+    paint(Style::new().bold(), text)
+}
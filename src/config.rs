@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use serde::Deserialize;
+
+static PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Config {
+    pub sources: HashMap<String, String>,
+    pub verify_pkg_config: Option<bool>,
+    pub prefer: Vec<String>,
+    pub search_cache_ttl: Option<u64>,
+    pub registry_dir: Option<PathBuf>,
+}
+
+pub fn set_config_path(path: PathBuf) {
+    let _ = PATH_OVERRIDE.set(path);
+}
+
+pub fn config_path() -> PathBuf {
+    if let Some(path) = PATH_OVERRIDE.get() {
+        return path.clone();
+    }
+    let config_home = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(env::var("HOME").unwrap_or_default()).join(".config"));
+    config_home.join("charoite/config.yaml")
+}
+
+pub fn cache_dir() -> PathBuf {
+    let cache_home = env::var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(env::var("HOME").unwrap_or_default()).join(".cache"));
+    cache_home.join("charoite")
+}
+
+impl Config {
+    pub fn load() -> Config {
+        let path = config_path();
+        let Ok(content) = fs::read_to_string(&path) else {
+            return Config::default();
+        };
+        match serde_yaml::from_str(&content) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Ignoring invalid config {}: {}", path.display(), e);
+                Config::default()
+            }
+        }
+    }
+
+    pub fn source_for(&self, repo: &str) -> Option<&str> {
+        self.sources
+            .iter()
+            .filter(|(pattern, _)| match pattern.strip_suffix('*') {
+                Some(prefix) => repo.starts_with(prefix),
+                None => repo == pattern.as_str(),
+            })
+            .max_by_key(|(pattern, _)| pattern.len())
+            .map(|(_, host)| host.as_str())
+    }
+}
@@ -0,0 +1,111 @@
+use std::fs;
+use crate::utils;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackageManager {
+    Apt,
+    Pacman,
+    Dnf,
+    Apk,
+    Zypper,
+    Pkg,
+}
+
+const ALL: [PackageManager; 6] = [
+    PackageManager::Apt,
+    PackageManager::Pacman,
+    PackageManager::Dnf,
+    PackageManager::Apk,
+    PackageManager::Zypper,
+    PackageManager::Pkg,
+];
+
+impl PackageManager {
+    pub fn detect() -> Option<PackageManager> {
+        from_os_release()
+            .filter(|m| utils::check_dependency(m.program()))
+            .or_else(|| {
+                ALL.into_iter()
+                    .filter(|m| *m != PackageManager::Pkg || utils::is_bsd())
+                    .find(|m| utils::check_dependency(m.program()))
+            })
+    }
+
+    pub fn program(self) -> &'static str {
+        match self {
+            PackageManager::Apt => "apt-get",
+            PackageManager::Pacman => "pacman",
+            PackageManager::Dnf => "dnf",
+            PackageManager::Apk => "apk",
+            PackageManager::Zypper => "zypper",
+            PackageManager::Pkg => "pkg",
+        }
+    }
+
+    pub fn install_args(self) -> &'static [&'static str] {
+        match self {
+            PackageManager::Apt | PackageManager::Dnf | PackageManager::Zypper | PackageManager::Pkg => &["install"],
+            PackageManager::Pacman => &["-S", "--needed"],
+            PackageManager::Apk => &["add"],
+        }
+    }
+
+    pub fn noconfirm_args(self) -> &'static [&'static str] {
+        match self {
+            PackageManager::Pacman => &["--noconfirm"],
+            PackageManager::Apk => &[],
+            _ => &["-y"],
+        }
+    }
+
+    pub fn package_name(self, dep: &str) -> String {
+        use PackageManager::*;
+        let name = match (dep, self) {
+            ("cc" | "gcc", Apt) => "build-essential",
+            ("cc" | "gcc", Pacman) => "base-devel",
+            ("cc" | "gcc", Apk) => "build-base",
+            ("cc", _) => "gcc",
+            ("pkg-config", Pacman | Apk) => "pkgconf",
+            ("pkg-config", Dnf) => "pkgconf-pkg-config",
+            ("pkg-config", Pkg) => "pkgconf",
+            ("ninja", Apt | Dnf) => "ninja-build",
+            ("ninja", Apk) => "samurai",
+            ("rustc", Apt) => "rustc",
+            ("rustc", _) => "rust",
+            ("python3", Pacman) => "python",
+            ("pip", Apt | Dnf | Zypper) => "python3-pip",
+            ("pip", Pacman) => "python-pip",
+            ("pip", Apk) => "py3-pip",
+            ("pip", Pkg) => "py311-pip",
+            ("cabal", Apk) => "cabal",
+            ("cabal", _) => "cabal-install",
+            ("stack", Apt) => "haskell-stack",
+            ("stack", Pkg) => "hs-stack",
+            ("nimble", Apt) => "nim",
+            _ => dep,
+        };
+        name.to_string()
+    }
+
+    pub fn command(self, packages: &[String]) -> String {
+        format!("{} {} {} {}", utils::get_privilege_command(), self.program(), self.install_args().join(" "), packages.join(" "))
+    }
+}
+
+fn from_os_release() -> Option<PackageManager> {
+    let content = fs::read_to_string("/etc/os-release").ok()?;
+    let ids: Vec<String> = content
+        .lines()
+        .filter_map(|l| l.strip_prefix("ID=").or_else(|| l.strip_prefix("ID_LIKE=")))
+        .flat_map(|v| v.trim_matches('"').split_whitespace().map(str::to_string).collect::<Vec<_>>())
+        .collect();
+    ids.iter().find_map(|id| match id.as_str() {
+        "debian" | "ubuntu" => Some(PackageManager::Apt),
+        "arch" => Some(PackageManager::Pacman),
+        "fedora" | "rhel" | "centos" => Some(PackageManager::Dnf),
+        "alpine" => Some(PackageManager::Apk),
+        "opensuse" | "suse" | "sles" => Some(PackageManager::Zypper),
+        "freebsd" => Some(PackageManager::Pkg),
+        _ => None,
+    })
+}
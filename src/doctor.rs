@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use ansi_term::Colour::{Green, Red, Yellow};
+use crate::color;
+use crate::registry::Registry;
+use crate::utils::{self, InstalledPackage, check_dependency};
+
+pub fn doctor() -> io::Result<()> {
+    println!("{}", color::bold("~> Checking environment"));
+    let mut issues = 0;
+
+    let path_dirs: Vec<PathBuf> = env::var_os("PATH")
+        .map(|p| env::split_paths(&p).collect())
+        .unwrap_or_default();
+    let local_bin = env::var("HOME").map(|h| PathBuf::from(h).join(".local/bin")).unwrap_or_default();
+    issues += utils::report_check("/usr/local/bin is in $PATH", path_dirs.iter().any(|d| d == Path::new("/usr/local/bin")));
+    issues += utils::report_check("~/.local/bin is in $PATH", path_dirs.contains(&local_bin));
+
+    for tool in ["git", "pkg-config", "make", "cc"] {
+        issues += utils::report_check(&format!("{} is installed", tool), check_dependency(tool));
+    }
+    let mut optional = vec!["cmake", "meson", "ninja", "cargo"];
+    if utils::is_bsd() {
+        optional.push("gmake");
+    }
+    for tool in optional {
+        if check_dependency(tool) {
+            utils::report_check(&format!("{} is installed", tool), true);
+        } else {
+            println!("{} {} is not installed (optional)", color::paint(Yellow, "[--]"), tool);
+        }
+    }
+
+    let registry_path = Registry::path();
+    let registry_dir = registry_path.parent().unwrap_or(Path::new("/"));
+    let probe_dir = registry_dir.ancestors().find(|d| d.exists()).unwrap_or(Path::new("/"));
+    if utils::has_write_access(probe_dir) {
+        utils::report_check(&format!("{} is writable", registry_dir.display()), true);
+    } else {
+        println!("{} {} requires {}", color::paint(Yellow, "[--]"), registry_dir.display(), utils::get_privilege_command());
+    }
+
+    if Registry::path().exists() {
+        match Registry::load() {
+            Ok(registry) => {
+                utils::report_check(&format!("installed.yaml parses ({} packages)", registry.packages().len()), true);
+                check_toolchains(registry.packages());
+            }
+            Err(e) => {
+                issues += utils::report_check(&format!("installed.yaml parses: {}", e), false);
+            }
+        }
+    } else {
+        println!("{} installed.yaml does not exist yet", color::paint(Yellow, "[--]"));
+    }
+
+    if issues == 0 {
+        println!("{}", color::paint(Green, "~> No issues found"));
+    } else {
+        println!("{}", color::paint(Red, format!("~> {} issue(s) found", issues)));
+    }
+    Ok(())
+}
+
+fn check_toolchains(installed: &[InstalledPackage]) {
+    let mut current: HashMap<String, Option<String>> = HashMap::new();
+    for pkg in installed {
+        for (tool, built_with) in &pkg.toolchain {
+            let now = current
+                .entry(tool.clone())
+                .or_insert_with(|| utils::tool_version(tool));
+            if now.as_ref() != Some(built_with) {
+                println!(
+                    "{} {} was built with {} ({}), consider rebuilding",
+                    color::paint(Yellow, "[--]"),
+                    pkg.name,
+                    built_with,
+                    now.as_deref().unwrap_or("now missing"),
+                );
+            }
+        }
+    }
+}
+
@@ -0,0 +1,146 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use ansi_term::Colour::{Green, Red};
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
+use serde::{Deserialize, Serialize};
+use crate::color;
+use crate::registry::Registry;
+use crate::utils;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HistoryEntry {
+    pub timestamp: String,
+    pub command: String,
+    pub package: String,
+    pub result: String,
+    pub duration_secs: u64,
+}
+
+fn history_path(local: bool) -> PathBuf {
+    let registry = if local { Registry::user_path() } else { Registry::path() };
+    registry.with_file_name("history.log")
+}
+
+pub fn record<T>(command: &str, package: &str, local: bool, result: &io::Result<T>, duration: Duration) {
+    let entry = HistoryEntry {
+        timestamp: Local::now().to_rfc3339(),
+        command: command.to_string(),
+        package: package.to_string(),
+        result: match result {
+            Ok(_) => "ok".to_string(),
+            Err(e) => format!("failed: {}", e),
+        },
+        duration_secs: duration.as_secs(),
+    };
+    let mut line = serde_json::to_string(&entry).unwrap();
+    line.push('\n');
+    if let Err(e) = append(&history_path(local), &line, local) {
+        eprintln!("Failed to write history: {}", e);
+    }
+}
+
+fn append(path: &Path, line: &str, local: bool) -> io::Result<()> {
+    let parent = path.parent().unwrap();
+    let direct = fs::create_dir_all(parent).and_then(|_| {
+        fs::OpenOptions::new().create(true).append(true).open(path)
+    });
+    match direct {
+        Ok(mut file) => file.write_all(line.as_bytes()),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied && !local => {
+            Command::new(utils::get_privilege_command())
+                .arg("mkdir")
+                .arg("-p")
+                .arg(parent)
+                .status()?;
+            let mut child = Command::new(utils::get_privilege_command())
+                .arg("tee")
+                .arg("-a")
+                .arg(path)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .spawn()?;
+            child.stdin.take().unwrap().write_all(line.as_bytes())?;
+            child.wait()?;
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub fn load() -> Vec<HistoryEntry> {
+    let (system, user) = (history_path(false), history_path(true));
+    let paths = if user == system { vec![system] } else { vec![system, user] };
+    let mut entries: Vec<HistoryEntry> = paths
+        .iter()
+        .flat_map(|path| fs::read_to_string(path).unwrap_or_default().lines().map(String::from).collect::<Vec<_>>())
+        .filter_map(|l| serde_json::from_str(&l).ok())
+        .collect();
+    entries.sort_by_key(|e| DateTime::parse_from_rfc3339(&e.timestamp).ok());
+    entries
+}
+
+pub fn parse_since(since: &str) -> io::Result<DateTime<Local>> {
+    let invalid = || io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Invalid --since '{}': expected e.g. 30m, 12h, 7d, 2w or YYYY-MM-DD", since),
+    );
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+        return midnight.and_local_timezone(Local).earliest().ok_or_else(invalid);
+    }
+    let split = since.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (amount, unit) = since.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "m" => TimeDelta::try_minutes(amount),
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        "w" => TimeDelta::try_weeks(amount),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+    Ok(Local::now() - duration)
+}
+
+pub fn show(limit: usize, since: Option<&str>, package: Option<&str>) -> io::Result<()> {
+    let since = since.map(parse_since).transpose()?;
+    let entries: Vec<HistoryEntry> = load()
+        .into_iter()
+        .filter(|e| package.is_none_or(|p| e.package == p || e.package.rsplit('/').next() == Some(p)))
+        .filter(|e| {
+            since.is_none_or(|since| DateTime::parse_from_rfc3339(&e.timestamp).is_ok_and(|t| t >= since))
+        })
+        .collect();
+    if entries.is_empty() {
+        if since.is_some() || package.is_some() {
+            println!("No matching history entries");
+        } else {
+            println!("No history recorded yet");
+        }
+        return Ok(());
+    }
+    println!("{:<17} {:<8} {:<30} {:<8} Result", "Date", "Action", "Package", "Time");
+    println!("{}", "-".repeat(80));
+    for entry in entries.iter().rev().take(limit).rev() {
+        let date = DateTime::parse_from_rfc3339(&entry.timestamp)
+            .map(|d| d.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|_| entry.timestamp.clone());
+        let result = if entry.result == "ok" {
+            color::paint(Green, &entry.result)
+        } else {
+            color::paint(Red, &entry.result)
+        };
+        println!(
+            "{:<17} {:<8} {:<30} {:<8} {}",
+            date,
+            entry.command,
+            entry.package,
+            format!("{}s", entry.duration_secs),
+            result
+        );
+    }
+    Ok(())
+}
@@ -1,17 +1,30 @@
+use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Read, Write};
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{Command, ExitStatus, Stdio};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Instant;
-use ansi_term::Colour::{Green, Red, Yellow};
+use ansi_term::Colour::{Cyan, Green, Red, Yellow};
+use serde::Deserialize;
 use serde_json;
-use serde_yaml;
 use sha2::{Sha256, Digest};
-use chrono::Local;
+use chrono::{Local, NaiveDate};
+use crate::color;
+use crate::archive;
+use crate::config::Config;
+use crate::distro::PackageManager;
+use crate::registry::Registry;
+use crate::sandbox::Sandbox;
+use crate::source::Source;
 use crate::utils::{self, InstalledPackage, check_dependency};
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BuildSystem {
     Make,
     Autotools,
@@ -21,29 +34,195 @@ pub enum BuildSystem {
     Ninja,
     Nimble,
     Stack,
+    Cabal,
     Pip,
+    #[serde(skip)]
+    Custom,
+    #[serde(skip)]
     Unknown,
 }
 
-struct InstallLocation {
-    bin_path: PathBuf,
-    elevate: bool,
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default = "default_manifest_build_system")]
+    build_system: BuildSystem,
+    #[serde(default)]
+    dependencies: Vec<String>,
+    #[serde(default)]
+    flags: Vec<String>,
 }
 
-pub fn install(
-    repo: &str,
-    local: bool,
-    gitlab: bool,
-    codeberg: bool,
-    branch: Option<&str>,
-    patches: Option<&Path>,
-    flags: &[String],
-    yes: bool,
-) -> io::Result<()> {
+#[derive(Deserialize)]
+struct ManifestOverride {
+    build_system: Option<BuildSystem>,
+    dependencies: Option<Vec<String>>,
+    flags: Option<Vec<String>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Jobs {
+    Auto,
+    Count(usize),
+}
+
+impl FromStr for Jobs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Jobs::Auto),
+            _ => match s.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(Jobs::Count(n)),
+                _ => Err(format!("invalid job count '{}' (expected a positive number or auto)", s)),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Installed,
+    Skipped,
+}
+
+fn default_manifest_build_system() -> BuildSystem {
+    BuildSystem::Make
+}
+
+pub struct InstallLocation {
+    pub bin_path: PathBuf,
+    pub elevate: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct InstallOptions {
+    pub local: bool,
+    pub source: Option<Source>,
+    pub script: bool,
+    pub branch: Option<String>,
+    pub commit: Option<String>,
+    pub depth: Option<u32>,
+    pub patches: Option<PathBuf>,
+    pub flags: Vec<String>,
+    pub no_default_flags: bool,
+    pub user_flags_first: bool,
+    pub fetch_only: bool,
+    pub prefetched: bool,
+    pub changes_since: Option<String>,
+    pub yes: bool,
+    pub show_log: bool,
+    pub stow: bool,
+    pub force: bool,
+    pub cmake_generator: Option<String>,
+    pub cmake_preset: Option<Option<String>>,
+    pub tarball: bool,
+    pub exclude: Vec<String>,
+    pub quiet_git: bool,
+    pub build_cmd: Option<String>,
+    pub install_cmd: Option<String>,
+    pub check_only: bool,
+    pub extras: bool,
+    pub no_verify_pkg_config: bool,
+    pub jobs: Option<Jobs>,
+    pub rustflags: Option<String>,
+    pub codegen_units: Option<usize>,
+    pub out_of_tree: bool,
+    pub verify_deps_versions: bool,
+    pub sandbox: Option<Sandbox>,
+    pub sandbox_image: Option<String>,
+    pub allow_downgrade: bool,
+    pub edit_flags: bool,
+    pub review: bool,
+    pub prefer: Vec<String>,
+    pub build_file: Option<String>,
+    pub strip_components: Option<usize>,
+    pub patch_urls: Vec<String>,
+    pub select_binary: bool,
+    pub group: Option<String>,
+    pub recurse_submodules: bool,
+    pub blobless: bool,
+    pub mode: Option<String>,
+    pub owner: Option<String>,
+    pub offline: bool,
+    pub resume: bool,
+    pub only_deps: bool,
+    pub target: Option<String>,
+    pub cmake_toolchain: Option<PathBuf>,
+    pub no_record: bool,
+    pub mirror: Option<String>,
+    pub prefix: Option<PathBuf>,
+    pub rename: Option<String>,
+    pub allow_dirty: bool,
+    pub output_dir: Option<PathBuf>,
+    pub build_target: Option<String>,
+    pub install_target: Option<String>,
+    pub strip: bool,
+    pub patch_from: Vec<String>,
+    pub docdir: Option<PathBuf>,
+    pub bindir: Option<PathBuf>,
+    pub libdir: Option<PathBuf>,
+    pub includedir: Option<PathBuf>,
+    pub no_docs: bool,
+}
+
+pub struct Installer {
+    repo: String,
+    opts: InstallOptions,
+}
+
+impl Installer {
+    pub fn new(repo: &str) -> Self {
+        Installer { repo: repo.to_string(), opts: InstallOptions::default() }
+    }
+
+    pub fn options(mut self, opts: InstallOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    pub fn source(mut self, source: Source) -> Self {
+        self.opts.source = Some(source);
+        self
+    }
+
+    pub fn local(mut self, local: bool) -> Self {
+        self.opts.local = local;
+        self
+    }
+
+    pub fn branch(mut self, branch: &str) -> Self {
+        self.opts.branch = Some(branch.to_string());
+        self
+    }
+
+    pub fn flags<I, S>(mut self, flags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.opts.flags = flags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn patches(mut self, patches: impl Into<PathBuf>) -> Self {
+        self.opts.patches = Some(patches.into());
+        self
+    }
+
+    pub fn yes(mut self, yes: bool) -> Self {
+        self.opts.yes = yes;
+        self
+    }
+
+    pub fn install(&self) -> io::Result<Outcome> {
+        install(&self.repo, &self.opts)
+    }
+}
+
+pub fn install(repo: &str, opts: &InstallOptions) -> io::Result<Outcome> {
     let start = Instant::now();
     let tmp = Path::new("/tmp/charoite");
     let builds = tmp.join("builds");
-    let etc = Path::new("/etc/charoite");
 
     for dir in [tmp, &builds] {
         if !dir.exists() {
@@ -51,23 +230,105 @@ pub fn install(
         }
     }
 
-    let source = if codeberg {
-        Some("codeberg")
-    } else if gitlab {
-        Some("gitlab")
+    if let Some(output_dir) = &opts.output_dir {
+        let output_dir = std::path::absolute(output_dir)?;
+        build_to_output_dir(repo, &output_dir, &tmp.join("output").join(std::process::id().to_string()), opts)?;
+        return Ok(Outcome::Skipped);
+    }
+
+    let config = Config::load();
+    validate_prefer(&opts.prefer)?;
+    validate_prefer(&config.prefer)?;
+    validate_permissions(opts)?;
+    if (opts.target.is_some() || opts.cmake_toolchain.is_some()) && opts.prefix.is_none() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Cross builds need --prefix so they don't land in the host's bin directory"));
+    }
+    if let Some(mirror) = &opts.mirror && !mirror.contains("{repo}") {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid --mirror '{}': expected a {{repo}} placeholder", mirror)));
+    }
+    let mut resolved = None;
+    if install_dirs(opts).iter().any(|dir| dir.is_relative()) {
+        let absolute = |dir: &Option<PathBuf>| dir.as_deref().map(std::path::absolute).transpose();
+        resolved = Some(InstallOptions {
+            docdir: absolute(&opts.docdir)?,
+            bindir: absolute(&opts.bindir)?,
+            libdir: absolute(&opts.libdir)?,
+            includedir: absolute(&opts.includedir)?,
+            ..opts.clone()
+        });
+    }
+    if let Some(file) = &opts.cmake_toolchain {
+        let file = fs::canonicalize(file)
+            .map_err(|e| io::Error::new(e.kind(), format!("Toolchain file {}: {}", file.display(), e)))?;
+        let base = resolved.take().unwrap_or_else(|| opts.clone());
+        resolved = Some(InstallOptions { cmake_toolchain: Some(file), ..base });
+    }
+    if let Some(dir) = &opts.patches && dir.is_relative() {
+        let dir = std::path::absolute(dir)?;
+        let base = resolved.take().unwrap_or_else(|| opts.clone());
+        resolved = Some(InstallOptions { patches: Some(dir), ..base });
+    }
+    if opts.jobs == Some(Jobs::Auto) {
+        let base = resolved.take().unwrap_or_else(|| opts.clone());
+        resolved = Some(InstallOptions { jobs: Some(Jobs::Count(auto_jobs())), ..base });
+    }
+    let opts = resolved.as_ref().unwrap_or(opts);
+    if opts.script && crate_name(repo, opts.source).is_none() && script_url(repo, true).is_none() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("--script needs an http(s) URL, got '{}'", repo)));
+    }
+    if opts.fetch_only && (crate_name(repo, opts.source).is_some() || script_url(repo, opts.script).is_some()) {
+        return Ok(Outcome::Skipped);
+    }
+    if let Some(name) = crate_name(repo, opts.source) {
+        return install_crate(&name, &tmp.join("crates"), opts);
+    }
+    if let Some(url) = script_url(repo, opts.script) {
+        return install_script(&url, &tmp.join("scripts"), opts);
+    }
+    let (repo, host) = if let Some(source @ (Source::Path | Source::Bundle)) = opts.source {
+        let kind = if source == Source::Path { "Source directory" } else { "Bundle" };
+        let path = fs::canonicalize(repo).map_err(|e| io::Error::new(e.kind(), format!("{} {}: {}", kind, repo, e)))?;
+        (path.to_string_lossy().to_string(), Some(source))
     } else {
-        None
+        parse_repo(repo)?
     };
-    let (_, domain) = match source {
-        Some("gitlab") => ("gitlab", "gitlab.com"),
-        Some("codeberg") => ("codeberg", "codeberg.org"),
-        _ => ("github", "github.com")
+    let source = match host.or(opts.source) {
+        Some(source) => source,
+        None => match config.source_for(&repo) {
+            Some(h) => h.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid config: {}", e)))?,
+            None => Source::default(),
+        },
+    };
+    let repo = repo.as_str();
+    let local = opts.local;
+
+    let repo_name = repo.rsplit('/').next().unwrap();
+    let repo_name = match source {
+        Source::Bundle => repo_name.trim_end_matches(".bundle"),
+        Source::Url => repo_name.trim_end_matches(".git"),
+        _ => repo_name,
     };
+    let name = opts.rename.as_deref().unwrap_or(repo_name);
+    if name.is_empty() || name.contains('/') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid name for --rename: '{}'", name)));
+    }
+    let build_dir = builds.join(name);
 
-    let repo_name = repo.split('/').last().unwrap();
-    let build_dir = builds.join(repo_name);
+    let fetched = build_dir.join(".git").exists() || opts.tarball && build_dir.exists();
+    let resuming = opts.resume && fetched;
+    if opts.resume && !resuming {
+        println!("{}", color::paint(Yellow, "Warning: no previous build to resume, starting fresh"));
+    }
+    let prefetched = opts.prefetched && fetched && !resuming && source != Source::Path;
+    if opts.offline && !resuming && !prefetched && !matches!(source, Source::Path | Source::Bundle) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Can't fetch {} with --offline, use --source path, --bundle or --resume", source.url(repo)),
+        ));
+    }
 
-    if build_dir.exists() {
+    #[allow(clippy::collapsible_if)]
+    if !resuming && !prefetched && build_dir.exists() {
         if let Err(e) = fs::remove_dir_all(&build_dir) {
             if e.kind() == io::ErrorKind::PermissionDenied {
                 let status = Command::new(utils::get_privilege_command())
@@ -76,95 +337,369 @@ pub fn install(
                     .arg(&build_dir)
                     .status();
                 if status.is_err() || !status.unwrap().success() {
-                    eprintln!("{}: Failed to clean previous build", Red.paint("Error"));
-                    return Ok(());
+                    eprintln!("{}: Failed to clean previous build", color::paint(Red, "Error"));
+                    return Ok(Outcome::Skipped);
                 }
             } else {
-                eprintln!("{}: Failed to clean previous build: {}", Red.paint("Error"), e);
-                return Ok(());
+                eprintln!("{}: Failed to clean previous build: {}", color::paint(Red, "Error"), e);
+                return Ok(Outcome::Skipped);
             }
         }
     }
 
-    println!("\x1b[1m~> Cloning repository: {}\x1b[0m", repo);
-    let mut git_clone = Command::new("git");
-    git_clone
-        .arg("clone")
-        .arg("--depth=1")
-        .arg(format!("https://{}/{}", domain, repo))
-        .arg(&build_dir);
+    let registry = Registry::load()?;
 
-    if let Some(b) = branch {
-        git_clone.arg("--branch").arg(b);
+    let archive_url = if opts.tarball { source.tarball_url(repo, opts.branch.as_deref()) } else { None };
+    if opts.tarball && archive_url.is_none() {
+        println!("{}", color::paint(Yellow, "Warning: this host doesn't support archive downloads, falling back to git clone"));
     }
 
-    let status = git_clone
-        .stdout(Stdio::null())
-        .status()
-        .expect("Git command failed");
+    let mut cloned_from = None;
+    let mut dirty = false;
+    if resuming {
+        println!("{}", color::bold(format!("~> Resuming previous build in {}", build_dir.display())));
+    } else if source == Source::Path {
+        dirty = has_uncommitted_changes(Path::new(repo));
+        if dirty && !opts.allow_dirty {
+            println!("{}", color::paint(Yellow, format!(
+                "Warning: {} has uncommitted changes, the binary won't match any commit (pass --allow-dirty to silence this)",
+                repo
+            )));
+        }
+        println!("{}", color::bold(format!("~> Copying source directory: {}", repo)));
+        fs::create_dir_all(&build_dir)?;
+        run_command("cp", &["-a", &format!("{}/.", repo), build_dir.to_str().unwrap()], false, None, None)?;
+        cloned_from = Some(repo.to_string());
+    } else if prefetched {
+        println!("{}", color::bold(format!("~> Using prefetched source in {}", build_dir.display())));
+        let origin = Command::new("git").args(["remote", "get-url", "origin"]).current_dir(&build_dir).output()?;
+        cloned_from = Some(match origin.status.success() {
+            true => String::from_utf8_lossy(&origin.stdout).trim().to_string(),
+            false => archive_url.unwrap_or_else(|| source.url(repo)),
+        });
+    } else if let Some(url) = archive_url {
+        println!("{}", color::bold(format!("~> Downloading tarball: {}", url)));
+        let archive_path = tmp.join(format!("{}.tar.gz", repo_name));
+        archive::download(&url, &archive_path)?;
+        cloned_from = Some(url.clone());
+        archive::extract(&archive_path, &build_dir, opts.strip_components)?;
+        let _ = fs::remove_file(&archive_path);
+        if build_dir.join(".gitmodules").exists() {
+            println!("{}", color::paint(Yellow, "Warning: tarballs don't include submodules; drop --tarball if the build fails"));
+        }
+    } else {
+        println!("{}", color::bold(format!("~> Cloning repository: {}", repo)));
+        let url = source.url(repo);
+        let depth = match (opts.commit.is_some(), opts.depth) {
+            _ if source == Source::Bundle => 0,
+            (true, Some(d)) if d > 0 => {
+                println!("{}", color::paint(Yellow, "Warning: --commit needs the full history, ignoring --depth"));
+                0
+            }
+            (true, _) => 0,
+            (false, d) => d.unwrap_or(if opts.blobless { 0 } else { 1 }),
+        };
+        let mut status = clone_repo(&url, &build_dir, depth, opts)?;
+        cloned_from = Some(url.clone());
+        if !status.success() && let Some(template) = &opts.mirror {
+            let _ = fs::remove_dir_all(&build_dir);
+            let mirror_url = template.replace("{repo}", repo);
+            println!("{}", color::paint(Yellow, format!("~> Clone from {} failed, trying mirror {}", source.domain(), mirror_url)));
+            status = clone_repo(&mirror_url, &build_dir, depth, opts)?;
+            cloned_from = Some(mirror_url);
+        }
 
-    if !status.success() {
-        eprintln!("{}", Red.paint("Failed to clone repository"));
-        return Ok(());
+        if !status.success() {
+            eprintln!("{}", color::paint(Red, "Failed to clone repository"));
+            let _ = fs::remove_dir_all(&build_dir);
+            if source == Source::Bundle {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("git clone failed; check that {} is a valid bundle", repo)));
+            }
+            if !utils::remote_exists(&url) {
+                let message = match source {
+                    Source::Url => format!("Repository {} not found", url),
+                    _ => format!("Repository {} not found on {}; check the --source host", repo, source.domain()),
+                };
+                return Err(io::Error::new(io::ErrorKind::NotFound, message));
+            }
+            if let Some(b) = &opts.branch {
+                return Err(io::Error::other(format!("git clone failed; check that branch {} exists", b)));
+            }
+            return Err(io::Error::other("git clone failed"));
+        }
+
+        if utils::get_git_commit_hash(&build_dir).is_err() {
+            let _ = fs::remove_dir_all(&build_dir);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Repository {} is empty", repo)));
+        }
+
+        if let Some(commit) = &opts.commit {
+            println!("~> Checking out {}", commit);
+            if let Err(e) = git(&build_dir, &["checkout", "--quiet", "--detach", commit]) {
+                let _ = fs::remove_dir_all(&build_dir);
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("Commit {} not found: {}", commit, e)));
+            }
+            if opts.recurse_submodules {
+                git(&build_dir, &["submodule", "update", "--init", "--recursive"])?;
+            }
+        } else if depth == 1 && opts.depth.is_none() && uses_git_version(&build_dir) {
+            println!("~> Build reads its version from git history, fetching full history");
+            if let Err(e) = git(&build_dir, &["fetch", "--quiet", "--unshallow", "--tags"]) {
+                println!("{}", color::paint(Yellow, format!("Warning: {}, building from the shallow clone", e)));
+            }
+        }
+
+        if !opts.recurse_submodules && build_dir.join(".gitmodules").exists() {
+            println!("{}", color::paint(Yellow, "Warning: repository uses submodules, fetching them (pass --recurse-submodules to do this during clone)"));
+            let mut update = Command::new("git");
+            update
+                .env("GIT_TERMINAL_PROMPT", "0")
+                .args(["submodule", "update", "--init", "--recursive", "--depth", "1"])
+                .current_dir(&build_dir);
+            if opts.quiet_git {
+                update.arg("--quiet").stdout(Stdio::null());
+            }
+            let status = update.status()?;
+            if !status.success() {
+                let _ = fs::remove_dir_all(&build_dir);
+                return Err(io::Error::other(format!("Failed to fetch submodules: git submodule update {}", status)));
+            }
+        }
+    }
+    if opts.fetch_only {
+        return Ok(Outcome::Skipped);
+    }
+    if !resuming && let Some(since) = &opts.changes_since && build_dir.join(".git").exists() {
+        show_changes(&build_dir, since);
+    }
+
+    let commit_date = utils::get_git_commit_date(&build_dir).ok();
+    if !opts.check_only
+        && let Some(existing) = registry.get_scoped(name, local && opts.prefix.is_none())
+        && is_downgrade(existing.last_commit_date.as_deref(), commit_date.as_deref())
+    {
+        println!("{}", color::paint(Yellow, format!(
+            "Warning: {} is older than the installed commit ({} < {})",
+            name,
+            commit_date.as_deref().unwrap_or_default(),
+            existing.last_commit_date.as_deref().unwrap_or_default(),
+        )));
+        if !opts.allow_downgrade && !opts.force {
+            return Err(io::Error::other("Refusing to downgrade, pass --allow-downgrade or --force"));
+        }
     }
 
-    if let Some(patches_dir) = patches {
+    if !resuming && !opts.patch_from.is_empty() {
+        if !build_dir.join(".git").exists() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "--patch-from needs a git checkout"));
+        }
+        for reference in &opts.patch_from {
+            apply_ref(&build_dir, reference)?;
+        }
+    }
+    if resuming && (opts.patches.is_some() || !opts.patch_urls.is_empty() || !opts.patch_from.is_empty()) {
+        println!("~> Skipping patches, they were applied before the interruption");
+    } else if let Some(patches_dir) = &opts.patches {
         apply_patches(&build_dir, patches_dir);
     }
+    if !resuming && !opts.patch_urls.is_empty() {
+        for patch in fetch_patches(&opts.patch_urls, &tmp.join("patches").join(repo_name))? {
+            apply_patch(&build_dir, &patch);
+        }
+    }
 
     env::set_current_dir(&build_dir)?;
-    let (build_system, build_file, deps, custom_flags) = detect_build_system();
+    let (build_system, build_file, mut deps, custom_flags) = if opts.build_cmd.is_some() {
+        (BuildSystem::Custom, None, parse_deps_file(Path::new(".charoite-deps")), vec![])
+    } else {
+        detect_build_system(if opts.prefer.is_empty() { &config.prefer } else { &opts.prefer }, opts.build_file.as_deref(), opts.yes || opts.force)?
+    };
 
     if build_system == BuildSystem::Unknown {
-        eprintln!("{}", Red.paint("Unsupported build system"));
-        return Ok(());
+        eprintln!("{}", color::paint(Red, "Unsupported build system"));
+        if opts.check_only {
+            let _ = fs::remove_dir_all(&build_dir);
+        }
+        return Ok(Outcome::Skipped);
     }
 
     println!("~> Build system: {}", match build_system {
-        BuildSystem::Make => Green.paint("Make"),
-        BuildSystem::Autotools => Green.paint("Autotools"),
-        BuildSystem::Cargo => Green.paint("Cargo"),
-        BuildSystem::Cmake => Green.paint("CMake"),
-        BuildSystem::Meson => Green.paint("Meson"),
-        BuildSystem::Ninja => Green.paint("Ninja"),
-        BuildSystem::Nimble => Green.paint("Nimble"),
-        BuildSystem::Stack => Green.paint("Stack"),
-        BuildSystem::Pip => Green.paint("Pip"),
+        BuildSystem::Make => color::paint(Green, "Make"),
+        BuildSystem::Autotools => color::paint(Green, "Autotools"),
+        BuildSystem::Cargo => color::paint(Green, "Cargo"),
+        BuildSystem::Cmake => color::paint(Green, "CMake"),
+        BuildSystem::Meson => color::paint(Green, "Meson"),
+        BuildSystem::Ninja => color::paint(Green, "Ninja"),
+        BuildSystem::Nimble => color::paint(Green, "Nimble"),
+        BuildSystem::Stack => color::paint(Green, "Stack"),
+        BuildSystem::Cabal => color::paint(Green, "Cabal"),
+        BuildSystem::Pip => color::paint(Green, "Pip"),
+        BuildSystem::Custom => color::paint(Green, "Custom"),
         _ => unreachable!()
     });
 
-    let uses_pkg_config = check_pkg_config_usage(build_system, build_file.as_ref());
-    if !uses_pkg_config {
-        println!("{}", Yellow.paint("Warning: This project doesn't use pkg-config for dependencies"));
-        if !yes {
-            print!("~> Proceed anyway? [y/N] ");
-            io::stdout().flush().unwrap();
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            if !input.trim().eq_ignore_ascii_case("y") {
-                println!("{}", Yellow.paint("Build cancelled by user"));
-                return Ok(());
+    let honours_dirs = matches!(build_system, BuildSystem::Autotools | BuildSystem::Cmake | BuildSystem::Meson);
+    let has_dir_overrides = opts.bindir.is_some() || opts.libdir.is_some() || opts.includedir.is_some();
+    if has_dir_overrides && !honours_dirs {
+        println!("{}", color::paint(Yellow, "Warning: --bindir, --libdir and --includedir only apply to autotools, cmake and meson builds"));
+    }
+
+    if opts.review
+        && let Some(file) = build_file.as_deref().filter(|f| *f == "charoite.json" || *f == "radon.json")
+        && !review_manifest(file, build_system, &deps, &custom_flags, opts)?
+    {
+        println!("{}", color::paint(Yellow, "Cancelled by user"));
+        return Ok(Outcome::Skipped);
+    }
+
+    if build_system == BuildSystem::Cmake && build_dir.join("CMakePresets.json").exists() {
+        let names: Vec<String> = cmake_presets(&build_dir).into_iter().map(|(name, _)| name).collect();
+        println!("~> Found CMakePresets.json ({})", if names.is_empty() { "no presets".to_string() } else { names.join(", ") });
+    }
+    if build_system == BuildSystem::Meson {
+        let pending = pending_meson_wraps(&build_dir);
+        if !pending.is_empty() {
+            println!("~> Meson subprojects to download: {}", pending.join(", "));
+        }
+    }
+    if opts.build_target.is_some() && !supports_build_target(build_system) {
+        println!("{}", color::paint(Yellow, "Warning: --build-target only applies to make, autotools, cmake, meson and ninja builds"));
+    }
+    if build_system != BuildSystem::Cargo && opts.target.is_some() {
+        println!("{}", color::paint(Yellow, "Warning: --target only applies to cargo builds, use --cmake-toolchain for cmake"));
+    }
+    if build_system != BuildSystem::Cmake && opts.cmake_toolchain.is_some() {
+        println!("{}", color::paint(Yellow, "Warning: --cmake-toolchain has no effect for this build system"));
+    }
+    if build_system != BuildSystem::Cargo && (opts.rustflags.is_some() || opts.codegen_units.is_some()) {
+        println!("{}", color::paint(Yellow, "Warning: --rustflags and --codegen-units only apply to cargo builds"));
+    }
+    match &opts.cmake_preset {
+        Some(_) if build_system != BuildSystem::Cmake => {
+            println!("{}", color::paint(Yellow, "Warning: --cmake-preset has no effect for this build system"));
+        }
+        Some(None) => {
+            let presets = cmake_presets(&build_dir);
+            if presets.is_empty() {
+                println!("{}", color::paint(Yellow, "No configure presets found"));
+            } else {
+                println!("{}", color::bold("Available presets:"));
+                for (name, _) in presets {
+                    println!("  {}", name);
+                }
             }
+            let _ = fs::remove_dir_all(&build_dir);
+            return Ok(Outcome::Skipped);
+        }
+        Some(Some(name)) if !cmake_presets(&build_dir).iter().any(|(n, _)| n == name) => {
+            let _ = fs::remove_dir_all(&build_dir);
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("CMake preset '{}' not found", name)));
+        }
+        _ => {}
+    }
+
+    if opts.stow && matches!(build_system, BuildSystem::Meson | BuildSystem::Ninja | BuildSystem::Pip) {
+        eprintln!("{}", color::paint(Red, "--stow is not supported for this build system"));
+        return Ok(Outcome::Skipped);
+    }
+    if build_system == BuildSystem::Ninja && (opts.local || opts.prefix.is_some()) {
+        let _ = fs::remove_dir_all(&build_dir);
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "build.ninja has a fixed install prefix, it can't be installed with --local, --prefix or --output-dir",
+        ));
+    }
+
+    let sandboxed;
+    let opts = match opts.sandbox {
+        Some(sandbox) => {
+            let image = sandbox_image(build_system, opts)?;
+            println!("~> Sandbox: {} ({})", sandbox, image);
+            sandboxed = InstallOptions { sandbox_image: Some(image), ..opts.clone() };
+            &sandboxed
+        }
+        None => opts,
+    };
+
+    if build_system == BuildSystem::Cmake {
+        match opts.cmake_generator.as_deref() {
+            Some("Ninja") => deps.push("ninja".to_string()),
+            Some("Unix Makefiles") => deps.push(utils::make_program(&build_dir).to_string()),
+            _ => {}
+        }
+    }
+
+    let verify_pkg_config = !opts.no_verify_pkg_config && config.verify_pkg_config.unwrap_or(true);
+    let uses_pkg_config = !verify_pkg_config || check_pkg_config_usage(build_system, build_file.as_ref());
+    if opts.offline {
+        for warning in offline_warnings(build_system, &build_dir) {
+            println!("{}", color::paint(Yellow, format!("Warning: {}", warning)));
+        }
+    }
+
+    if opts.only_deps {
+        let result = install_missing_deps(&deps, opts);
+        let _ = fs::remove_dir_all(&build_dir);
+        return result.map(|_| Outcome::Skipped);
+    }
+
+    if opts.check_only {
+        let problems = preflight_report(build_system, uses_pkg_config, &deps, opts.verify_deps_versions);
+        let _ = fs::remove_dir_all(&build_dir);
+        if problems == 0 {
+            println!("{}", color::paint(Green, "~> Pre-flight check passed"));
+            return Ok(Outcome::Skipped);
+        }
+        return Err(io::Error::other(format!("Pre-flight check found {} problem(s)", problems)));
+    }
+
+    if build_system != BuildSystem::Custom && !uses_pkg_config {
+        println!("{}", color::paint(Yellow, "Warning: This project doesn't use pkg-config for dependencies"));
+        if !confirm(opts, Guard::Benign, "Proceed anyway?")? {
+            println!("{}", color::paint(Yellow, "Build cancelled by user"));
+            return Ok(Outcome::Skipped);
         }
     }
 
-    utils::check_deps(&deps);
+    if opts.sandbox.is_none() {
+        utils::check_deps(&deps, opts.verify_deps_versions)?;
+    }
 
-    let mut final_flags = custom_flags;
-    final_flags.extend(flags.iter().map(|s| s.to_string()));
+    let default_flags = if opts.no_default_flags { Vec::new() } else { custom_flags };
+    let user_flags = opts.flags.iter().map(|s| s.to_string());
+    let mut final_flags: Vec<String> = if opts.user_flags_first {
+        user_flags.chain(default_flags).collect()
+    } else {
+        default_flags.into_iter().chain(user_flags).collect()
+    };
+    if opts.edit_flags {
+        final_flags = edit_flags(build_system, &final_flags, &tmp.join(format!("{}-flags.txt", repo_name)))?;
+    }
 
     println!("~> Building with flags: {:?}", final_flags);
-    build_project(build_system, &build_dir, &final_flags, build_file.as_ref())?;
+    let log_path = tmp.join(format!("{}-build.log", repo_name));
+    fs::write(&log_path, "")?;
+    if let Err(e) = build_project(build_system, &build_dir, &final_flags, &log_path, opts) {
+        eprintln!("{}: build failed; see {}", color::paint(Red, "Error"), log_path.display());
+        return Err(e);
+    }
+    if opts.show_log {
+        println!("~> Build log: {}", log_path.display());
+    }
 
+    #[allow(clippy::io_other_error)]
     if build_system == BuildSystem::Pip {
         let requirements_file = build_dir.join("requirements.txt");
         if requirements_file.exists() {
             println!("~> Installing Python dependencies");
-            let pip_command = if local {
+            let mut pip_command = if local {
                 vec!["pip", "install", "--user", "-r", requirements_file.to_str().unwrap()]
             } else {
                 vec!["pip", "install", "-r", requirements_file.to_str().unwrap()]
             };
+            if opts.offline {
+                pip_command.insert(2, "--no-index");
+            }
             let status = if local {
                 Command::new(pip_command[0])
                     .args(&pip_command[1..])
@@ -176,206 +711,1171 @@ pub fn install(
             };
             if let Ok(status) = status {
                 if !status.success() {
-                    eprintln!("{}", Red.paint("Failed to install Python dependencies"));
+                    eprintln!("{}", color::paint(Red, "Failed to install Python dependencies"));
                     return Err(io::Error::new(io::ErrorKind::Other, "Failed to install Python dependencies"));
                 }
             } else {
-                eprintln!("{}", Red.paint("Failed to run pip"));
+                eprintln!("{}", color::paint(Red, "Failed to run pip"));
                 return Err(io::Error::new(io::ErrorKind::Other, "Failed to run pip"));
             }
         }
     }
 
     println!("~> Installing...");
-    let install_location = get_install_path(local);
-    install_project(build_system, &install_location, &build_dir, repo_name)?;
+    let mut install_location = get_install_path(local, opts.prefix.as_deref());
+    install_location.elevate |= install_dirs(opts).iter().any(|dir| needs_elevation(dir));
+    let bin_dir = opts.bindir.clone().filter(|_| honours_dirs).unwrap_or_else(|| install_location.bin_path.clone());
+    if !install_location.bin_path.exists() {
+        run_command("mkdir", &["-p", install_location.bin_path.to_str().unwrap()], install_location.elevate, None, None)?;
+    }
+    let stow_dir = opts.stow.then(|| stow_path(&install_location, name));
+    if !opts.exclude.is_empty() && stow_dir.is_none() && matches!(build_system, BuildSystem::Custom | BuildSystem::Pip) {
+        println!("{}", color::paint(Yellow, "Warning: --exclude has no effect for this build system"));
+    }
+    let mut files = if let Some(stow_dir) = &stow_dir {
+        install_stowed(build_system, &install_location, stow_dir, &build_dir, repo_name, opts)?
+    } else {
+        install_project(build_system, &install_location, &build_dir, repo_name, opts)?
+    };
+    let bin_location = InstallLocation { bin_path: bin_dir.clone(), elevate: install_location.elevate };
+    if name != repo_name && let Err(e) = rename_binary(&mut files, &bin_location, repo_name, name, opts) {
+        remove_installed(&files, install_location.elevate);
+        return Err(e);
+    }
 
-    if !local {
+    let stripped_by_make = matches!(build_system, BuildSystem::Make | BuildSystem::Autotools)
+        && opts.build_target.is_none()
+        && make_install_target(build_system, &build_dir, opts) == "install-strip";
+    if opts.strip && !stripped_by_make {
+        strip_binaries(&files, install_location.elevate);
+    }
+
+    if opts.mode.is_some() || opts.owner.is_some() {
+        let mut binaries: Vec<&PathBuf> = files.iter().filter(|f| f.parent() == Some(bin_dir.as_path())).collect();
+        let fallback = bin_dir.join(name);
+        if binaries.is_empty() && fallback.exists() {
+            binaries.push(&fallback);
+        }
+        if let Err(e) = apply_permissions(&binaries, opts, install_location.elevate) {
+            remove_installed(&files, install_location.elevate);
+            return Err(e);
+        }
+    }
+
+    if opts.extras {
+        match install_extras(&build_dir, &install_location) {
+            Ok(extras) => files.extend(extras),
+            Err(e) => {
+                eprintln!("{}: installing extras failed, removing installed files", color::paint(Red, "Error"));
+                remove_installed(&files, install_location.elevate);
+                return Err(e);
+            }
+        }
+    }
+
+    if !opts.no_record {
         let mut hasher = Sha256::new();
+        #[allow(clippy::collapsible_if)]
         if let Some(bf) = &build_file {
-            if let Ok(content) = fs::read(&build_dir.join(bf)) {
+            if let Ok(content) = fs::read(build_dir.join(bf)) {
                 hasher.update(&content);
             }
         }
         let hash = format!("{:x}", hasher.finalize());
-        let mut version = None;
-        if build_system == BuildSystem::Cargo {
-            if let Ok(cargo_toml) = fs::read_to_string(build_dir.join("Cargo.toml")) {
-                if let Some(v) = cargo_toml.lines().find(|l| l.starts_with("version = ")) {
-                    version = v.split('"').nth(1).map(|s| s.to_string());
-                }
-            }
-        }
-        
-        let commit_hash = utils::get_git_commit_hash(&build_dir).ok();
-        let commit_date = utils::get_git_commit_date(&build_dir).ok();
-        
-        let installed_binary_path = install_location.bin_path.join(repo_name);
-        
-        update_installed_packages(
-            repo_name,
-            source,
-            build_system,
-            &installed_binary_path,
-            build_file.as_ref(),
-            Some(hash),
+        let version = if build_system == BuildSystem::Cargo { cargo_version(&build_dir, repo_name) } else { None };
+
+        let installed_binary_path = match &opts.build_target {
+            Some(_) if name == repo_name && files.len() == 1 => files[0].clone(),
+            _ => bin_dir.join(name),
+        };
+
+        let mut registry = Registry::load()?;
+        let pinned = registry.get_scoped(name, opts.local && opts.prefix.is_none()).is_some_and(|p| p.pinned);
+        registry.upsert(InstalledPackage {
+            name: name.to_string(),
+            repo: Some(repo.to_string()),
+            branch: opts.branch.clone(),
+            source: Some(source),
+            build_system: format!("{:?}", build_system),
+            location: installed_binary_path.to_string_lossy().to_string(),
+            build_file,
+            hash: Some(hash),
             version,
-            commit_hash,
-            Some(Local::now().format("%y-%m-%d").to_string()),
-            commit_date,
-        );
+            last_commit_hash: utils::get_git_commit_hash(&build_dir).ok(),
+            install_date: Some(Local::now().format("%y-%m-%d").to_string()),
+            last_commit_date: commit_date,
+            stow: stow_dir.map(|d| d.to_string_lossy().to_string()),
+            toolchain: utils::collect_toolchain(toolchain_tools(build_system)),
+            build_cmd: opts.build_cmd.clone(),
+            install_cmd: opts.install_cmd.clone(),
+            files: utils::relative_paths(&files, &utils::install_prefix(local, opts.prefix.as_deref())),
+            group: opts.group.clone(),
+            mode: opts.mode.clone(),
+            owner: opts.owner.clone(),
+            prefix: opts.prefix.as_ref().map(|p| p.to_string_lossy().to_string()),
+            pinned,
+            build_duration: Some(start.elapsed().as_secs()),
+            target: opts.target.clone(),
+            cmake_toolchain: opts.cmake_toolchain.as_ref().map(|p| p.to_string_lossy().to_string()),
+            binary_hash: utils::file_sha256(&installed_binary_path).ok(),
+            mirror: opts.mirror.clone(),
+            cloned_from,
+            local: local && opts.prefix.is_none(),
+            dirty,
+            patch_from: opts.patch_from.clone(),
+            docdir: opts.docdir.as_ref().map(|p| p.to_string_lossy().to_string()),
+            no_docs: opts.no_docs,
+            bindir: opts.bindir.as_ref().map(|p| p.to_string_lossy().to_string()),
+            libdir: opts.libdir.as_ref().map(|p| p.to_string_lossy().to_string()),
+            includedir: opts.includedir.as_ref().map(|p| p.to_string_lossy().to_string()),
+            extras: opts.extras,
+            flags: if opts.edit_flags { final_flags.clone() } else { opts.flags.clone() },
+            no_default_flags: opts.no_default_flags || opts.edit_flags,
+            user_flags_first: opts.user_flags_first,
+            build_target: opts.build_target.clone(),
+            install_target: opts.install_target.clone(),
+            patches: opts.patches.as_ref().map(|p| p.to_string_lossy().to_string()),
+            patch_urls: opts.patch_urls.clone(),
+            rustflags: opts.rustflags.clone(),
+            codegen_units: opts.codegen_units,
+        });
+        registry.save()?;
     }
 
     println!("{} in {}s", 
-        Green.paint("~> INSTALL FINISHED"), 
+        color::paint(Green, "~> INSTALL FINISHED"), 
         start.elapsed().as_secs()
     );
 
-    if !local {
-        println!("{}", Yellow.paint("Warning: charoite installs packages to /usr/local/bin by default.\nIf /usr/local/bin is not in your $PATH, you may need to add it."));
-    } else {
-        println!("{}", Green.paint("Installed to ~/.local/bin. Make sure this directory is in your PATH."));
+    if opts.prefix.is_none() {
+        if !local {
+            println!("{}", color::paint(Yellow, "Warning: charoite installs packages to /usr/local/bin by default.\nIf /usr/local/bin is not in your $PATH, you may need to add it."));
+        } else {
+            println!("{}", color::paint(Green, "Installed to ~/.local/bin. Make sure this directory is in your PATH."));
+        }
     }
-    Ok(())
+    warn_if_shadowed(&bin_dir, name);
+    Ok(Outcome::Installed)
 }
 
-fn detect_build_system() -> (BuildSystem, Option<String>, Vec<String>, Vec<String>) {
-    let mut build_files = Vec::new();
-    if Path::new("radon.json").exists() {
-        build_files.push(("radon.json", BuildSystem::Unknown));
-    }
-    if Path::new("charoite.json").exists() {
-        build_files.push(("charoite.json", BuildSystem::Unknown));
-    }
-    if Path::new("Cargo.toml").exists() {
-        build_files.push(("Cargo.toml", BuildSystem::Cargo));
-    }
-    if Path::new("Makefile").exists() {
-        build_files.push(("Makefile", BuildSystem::Make));
-    }
-    if Path::new("configure").exists() {
-        build_files.push(("configure", BuildSystem::Autotools));
-    }
-    if Path::new("CMakeLists.txt").exists() {
-        build_files.push(("CMakeLists.txt", BuildSystem::Cmake));
-    }
-    if Path::new("meson.build").exists() {
-        build_files.push(("meson.build", BuildSystem::Meson));
+fn edit_flags(build_system: BuildSystem, flags: &[String], path: &Path) -> io::Result<Vec<String>> {
+    let mut content = format!("# {:?} flags for this build, one per line.\n# Lines starting with # are ignored. Save and quit to start the build.\n", build_system);
+    for flag in flags {
+        content.push_str(flag);
+        content.push('\n');
     }
-    if Path::new("build.ninja").exists() {
-        build_files.push(("build.ninja", BuildSystem::Ninja));
+    fs::write(path, content)?;
+    let editor = env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or_else(|_| "vi".to_string());
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("Editor {} exited with {}", editor, status)));
     }
-    if fs::read_dir(".")
-        .unwrap()
-        .any(|e| e.unwrap().path().extension().map(|e| e == "nimble").unwrap_or(false)) 
-    {
-        build_files.push(("*.nimble", BuildSystem::Nimble));
+    let edited = fs::read_to_string(path)?
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.to_string())
+        .collect();
+    let _ = fs::remove_file(path);
+    Ok(edited)
+}
+
+fn offline_warnings(build_system: BuildSystem, build_dir: &Path) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if build_system == BuildSystem::Cargo && !cargo_vendored(build_dir) {
+        let cached = Command::new("cargo")
+            .args(["fetch", "--offline", "--quiet"])
+            .current_dir(build_dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success());
+        if !cached {
+            warnings.push("cargo dependencies are not vendored or cached in ~/.cargo, the build likely needs network".to_string());
+        }
     }
-    if Path::new("stack.yaml").exists() {
-        build_files.push(("stack.yaml", BuildSystem::Stack));
+    if build_system == BuildSystem::Pip || build_dir.join("requirements.txt").exists() {
+        warnings.push("pip runs with --no-index, python dependencies must already be installed".to_string());
     }
-    if Path::new("requirements.txt").exists() {
-        build_files.push(("requirements.txt", BuildSystem::Pip));
+    if build_system == BuildSystem::Meson {
+        let pending = pending_meson_wraps(build_dir);
+        if !pending.is_empty() {
+            warnings.push(format!("meson subprojects not downloaded yet ({}), setup runs with --wrap-mode=nodownload and will fail without them", pending.join(", ")));
+        }
     }
-    let (build_file, build_system) = if !build_files.is_empty() {
-        if build_files.len() > 1 {
-            println!("\x1b[1;36mMultiple build files detected. Select one:\x1b[0m");
-            for (i, (file, _)) in build_files.iter().enumerate() {
-                println!("{}: {}", i + 1, file);
-            }
-            io::stdout().flush().unwrap();
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            let choice: usize = input.trim().parse().unwrap_or(0);
-            if choice > 0 && choice <= build_files.len() {
-                build_files[choice - 1]
-            } else {
-                return (BuildSystem::Unknown, None, vec![], vec![]);
-            }
+    if build_dir.join("package.json").exists() && !build_dir.join("node_modules").exists() {
+        let lockfile = ["package-lock.json", "yarn.lock", "pnpm-lock.yaml"].iter().any(|f| build_dir.join(f).exists());
+        warnings.push(if lockfile {
+            "node_modules is missing, npm will need network to install the locked dependencies".to_string()
         } else {
-            build_files[0]
-        }
-    } else {
-        return (BuildSystem::Unknown, None, vec![], vec![]);
-    };
-    let (deps, flags) = match build_system {
-        BuildSystem::Make => (parse_make_deps(Path::new(".")), vec![]),
-        BuildSystem::Autotools => (parse_autotools_deps(Path::new(".")), vec![]),
-        BuildSystem::Cargo => (parse_cargo_deps(Path::new(".")), vec![]),
-        BuildSystem::Cmake => (vec!["cmake".to_string()], vec![]),
-        BuildSystem::Meson => (vec!["meson".to_string(), "ninja".to_string()], vec![]),
-        BuildSystem::Ninja => (vec!["ninja".to_string()], vec![]),
-        BuildSystem::Nimble => (vec!["nim".to_string(), "nimble".to_string()], vec![]),
-        BuildSystem::Stack => (vec!["stack".to_string()], vec![]),
-        BuildSystem::Pip => (vec!["pip".to_string()], vec![]),
-        _ => (vec![], vec![]),
+            "node_modules and a lockfile are missing, npm dependencies need network".to_string()
+        });
+    }
+    warnings
+}
+
+fn pending_meson_wraps(build_dir: &Path) -> Vec<String> {
+    let subprojects = build_dir.join("subprojects");
+    let Ok(entries) = fs::read_dir(&subprojects) else {
+        return Vec::new();
     };
-    if build_file == "radon.json" || build_file == "charoite.json" {
-        let (bs, d, f) = parse_charoite_json(Path::new(build_file));
-        return (bs, Some(build_file.to_string()), d, f);
-    }
-    (build_system, Some(build_file.to_string()), deps, flags)
-}
-
-fn parse_charoite_json(path: &Path) -> (BuildSystem, Vec<String>, Vec<String>) {
-    let file = std::fs::File::open(path).expect("Failed to open charoite.json");
-    let reader = std::io::BufReader::new(file);
-    let json: serde_json::Value = serde_json::from_reader(reader).expect("Invalid charoite.json");
-    let build_system = match json["build_system"].as_str().unwrap_or("make") {
-        "make" => BuildSystem::Make,
-        "autotools" => BuildSystem::Autotools,
-        "cargo" => BuildSystem::Cargo,
-        "cmake" => BuildSystem::Cmake,
-        "meson" => BuildSystem::Meson,
-        "ninja" => BuildSystem::Ninja,
-        "nimble" => BuildSystem::Nimble,
-        "stack" => BuildSystem::Stack,
-        "pip" => BuildSystem::Pip,
-        _ => BuildSystem::Unknown,
-    };
-    let deps = json["dependencies"].as_array().map(|arr| {
-        arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect()
-    }).unwrap_or_default();
-    let flags = json["flags"].as_array().map(|arr| {
-        arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect()
-    }).unwrap_or_default();
-    (build_system, deps, flags)
+    let mut pending: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "wrap"))
+        .filter_map(|wrap| {
+            let name = wrap.file_stem()?.to_string_lossy().to_string();
+            let directory = fs::read_to_string(&wrap)
+                .unwrap_or_default()
+                .lines()
+                .filter_map(|l| l.split_once('='))
+                .find(|(key, _)| key.trim() == "directory")
+                .map(|(_, value)| value.trim().to_string())
+                .unwrap_or_else(|| name.clone());
+            (!subprojects.join(directory).exists()).then_some(name)
+        })
+        .collect();
+    pending.sort();
+    pending
 }
 
-fn parse_make_deps(dir: &Path) -> Vec<String> {
-    let makefiles = ["Makefile", "makefile", "GNUMakefile"];
-    let found_file = makefiles.iter().find(|f| dir.join(f).exists()).unwrap_or(&"Makefile");
-    let makefile = fs::read_to_string(dir.join(found_file)).unwrap_or_default();
-    makefile.lines().find(|l| l.contains("# DEPENDENCIES:")).map(|l| {
-        l.split(':').nth(1).unwrap().split(',').map(|s| s.trim().to_string()).collect()
-    }).unwrap_or_default()
+fn cargo_vendored(build_dir: &Path) -> bool {
+    build_dir.join("vendor").is_dir()
+        || [".cargo/config.toml", ".cargo/config"]
+            .iter()
+            .any(|f| fs::read_to_string(build_dir.join(f)).is_ok_and(|c| c.contains("vendored-sources")))
 }
 
-fn parse_autotools_deps(dir: &Path) -> Vec<String> {
-    let configure = fs::read_to_string(dir.join("configure")).unwrap_or_default();
-    let mut deps = Vec::new();
-    if configure.contains("PKG_CHECK_MODULES") {
-        deps.push("pkg-config".to_string());
-    } else {
-        println!("{}", Yellow.paint("Warning: Autotools project doesn't use pkg-config"));
+const GIT_VERSION_FILES: [&str; 9] = ["Makefile", "GNUmakefile", "configure.ac", "CMakeLists.txt", "meson.build", "build.rs", "Cargo.toml", "setup.py", "version.sh"];
+const GIT_VERSION_MARKERS: [&str; 6] = ["git describe", "git rev-list", "vcs_tag(", "vergen", "git-version", "setuptools_scm"];
+
+fn clone_repo(url: &str, build_dir: &Path, depth: u32, opts: &InstallOptions) -> io::Result<ExitStatus> {
+    let mut git_clone = Command::new("git");
+    git_clone
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .arg("clone");
+    if depth > 0 {
+        git_clone.arg(format!("--depth={}", depth));
     }
-    if configure.contains("AC_PROG_CC") {
-        deps.push("gcc".to_string());
+    if opts.blobless {
+        git_clone.arg("--filter=blob:none");
     }
-    if configure.contains("AC_PROG_CXX") {
+    git_clone
+        .arg(url)
+        .arg(build_dir);
+
+    if let Some(b) = &opts.branch {
+        git_clone.arg("--branch").arg(b);
+    }
+
+    if opts.quiet_git {
+        git_clone.arg("--quiet").stdout(Stdio::null());
+    } else {
+        git_clone.arg("--progress");
+    }
+
+    if opts.recurse_submodules {
+        git_clone.arg("--recurse-submodules");
+        if depth > 0 {
+            git_clone.arg("--shallow-submodules");
+        }
+    }
+
+    git_clone.status()
+}
+
+fn has_uncommitted_changes(dir: &Path) -> bool {
+    Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(dir)
+        .stderr(Stdio::null())
+        .output()
+        .is_ok_and(|o| o.status.success() && !o.stdout.is_empty())
+}
+
+fn uses_git_version(build_dir: &Path) -> bool {
+    GIT_VERSION_FILES
+        .iter()
+        .filter_map(|f| fs::read_to_string(build_dir.join(f)).ok())
+        .any(|content| GIT_VERSION_MARKERS.iter().any(|m| content.contains(m)))
+}
+
+fn git(dir: &Path, args: &[&str]) -> io::Result<()> {
+    let output = Command::new("git")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .args(args)
+        .current_dir(dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("git {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim())))
+    }
+}
+
+const MAX_CHANGES_SHOWN: usize = 30;
+
+fn show_changes(build_dir: &Path, since: &str) {
+    let short: String = since.chars().take(7).collect();
+    let has_commit = || git(build_dir, &["cat-file", "-e", &format!("{}^{{commit}}", since)]).is_ok();
+    if !has_commit() && build_dir.join(".git/shallow").exists() {
+        println!("~> Fetching history to show changes since {}", short);
+        let _ = git(build_dir, &["fetch", "--quiet", "--unshallow"]);
+    }
+    if !has_commit() {
+        println!("{}", color::paint(Yellow, format!("Warning: {} is not in the new history (force-pushed?), can't show changes", short)));
+        return;
+    }
+    let output = Command::new("git")
+        .args(["log", "--oneline", "--no-decorate", &format!("{}..HEAD", since)])
+        .current_dir(build_dir)
+        .output();
+    let Ok(output) = output.map(|o| String::from_utf8_lossy(&o.stdout).to_string()) else { return };
+    let commits: Vec<&str> = output.lines().collect();
+    if commits.is_empty() {
+        println!("~> No new commits since {}", short);
+        return;
+    }
+    println!("{}", color::bold(format!("~> {} commit(s) since {}:", commits.len(), short)));
+    for commit in commits.iter().take(MAX_CHANGES_SHOWN) {
+        println!("  {}", commit);
+    }
+    if commits.len() > MAX_CHANGES_SHOWN {
+        println!("  ... and {} more", commits.len() - MAX_CHANGES_SHOWN);
+    }
+}
+
+fn apply_ref(build_dir: &Path, reference: &str) -> io::Result<()> {
+    println!("~> Applying commits from {}", reference);
+    let local = git(build_dir, &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", reference)]).is_ok();
+    if !local {
+        if build_dir.join(".git/shallow").exists() {
+            git(build_dir, &["fetch", "--quiet", "--unshallow"])?;
+        }
+        git(build_dir, &["fetch", "--quiet", "origin", reference])
+            .map_err(|e| io::Error::new(io::ErrorKind::NotFound, format!("Ref {} not found: {}", reference, e)))?;
+    }
+    let range = format!("HEAD..{}", if local { reference } else { "FETCH_HEAD" });
+    git(build_dir, &["cherry-pick", "--no-commit", &range])
+        .map_err(|e| io::Error::other(format!("Commits from {} don't apply cleanly: {}", reference, e)))
+}
+
+fn install_missing_deps(deps: &[String], opts: &InstallOptions) -> io::Result<()> {
+    let missing: Vec<String> = utils::find_missing_deps(deps).iter().map(|d| utils::split_dep(d).0.to_string()).collect();
+    if missing.is_empty() {
+        println!("{}", color::paint(Green, "~> All dependencies are present"));
+        return Ok(());
+    }
+    println!("~> Missing dependencies: {}", missing.join(", "));
+    let Some(manager) = PackageManager::detect() else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "No supported package manager found (apt, pacman, dnf, apk, zypper), install the dependencies manually"));
+    };
+    let packages: Vec<String> = missing.iter().map(|d| manager.package_name(d)).collect();
+    println!("~> {}", manager.command(&packages));
+    if !confirm(opts, Guard::Benign, &format!("Install {} package(s) with {}?", packages.len(), manager.program()))? {
+        println!("{}", color::paint(Yellow, "Cancelled by user"));
+        return Ok(());
+    }
+    let mut install_args = manager.install_args().to_vec();
+    install_args.extend(manager.noconfirm_args());
+    install_args.extend(packages.iter().map(|p| p.as_str()));
+    run_command(manager.program(), &install_args, true, None, None)?;
+    let still_missing = utils::find_missing_deps(deps);
+    if !still_missing.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Still missing after install: {} (the distro package may have a different name)", still_missing.join(", ")),
+        ));
+    }
+    println!("{}", color::paint(Green, "~> Dependencies installed"));
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Guard {
+    Benign,
+    Destructive,
+}
+
+fn confirm(opts: &InstallOptions, guard: Guard, question: &str) -> io::Result<bool> {
+    match guard {
+        _ if opts.force => return Ok(true),
+        Guard::Benign if opts.yes => return Ok(true),
+        Guard::Destructive if opts.yes || !io::stdin().is_terminal() => {
+            return Err(io::Error::other(format!("\"{}\" needs confirmation, pass --force to proceed", question)));
+        }
+        _ => {}
+    }
+    print!("~> {} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().eq_ignore_ascii_case("y"))
+}
+
+fn review_manifest(file: &str, build_system: BuildSystem, deps: &[String], flags: &[String], opts: &InstallOptions) -> io::Result<bool> {
+    let list = |items: &[String]| if items.is_empty() { "(none)".to_string() } else { items.join(" ") };
+    println!("{}", color::bold(format!("~> {} asks for:", file)));
+    println!("  build system: {:?}", build_system);
+    println!("  dependencies: {}", list(deps));
+    println!("  flags:        {}", list(flags));
+    confirm(opts, Guard::Benign, &format!("Build with this {}?", file))
+}
+
+fn is_downgrade(installed: Option<&str>, new: Option<&str>) -> bool {
+    let parse = |date: Option<&str>| date.and_then(|d| NaiveDate::parse_from_str(d, "%y-%m-%d").ok());
+    match (parse(installed), parse(new)) {
+        (Some(installed), Some(new)) => new < installed,
+        _ => false,
+    }
+}
+
+fn preflight_report(build_system: BuildSystem, uses_pkg_config: bool, deps: &[String], verify_versions: bool) -> usize {
+    println!("{}", color::bold("~> Pre-flight check"));
+    let mut problems = utils::report_check(&format!("build system: {:?}", build_system), true);
+    if build_system != BuildSystem::Custom && !uses_pkg_config {
+        println!("{} project doesn't use pkg-config for dependencies", color::paint(Yellow, "[--]"));
+    }
+    let missing = utils::find_missing_deps(deps);
+    for dep in deps {
+        problems += utils::report_check(&format!("dependency: {}", dep), !missing.contains(dep));
+    }
+    if !missing.is_empty() && let Some(manager) = PackageManager::detect() {
+        let packages: Vec<String> = missing.iter().map(|d| manager.package_name(utils::split_dep(d).0)).collect();
+        println!("     try: {}", manager.command(&packages));
+    }
+    if verify_versions {
+        for dep in utils::find_outdated_deps(deps) {
+            problems += utils::report_check(&format!("dependency version: {}", dep), false);
+        }
+    }
+    problems
+}
+
+fn toolchain_tools(build_system: BuildSystem) -> &'static [&'static str] {
+    match build_system {
+        BuildSystem::Make => &["cc", "make"],
+        BuildSystem::Autotools => &["cc", "make", "autoconf"],
+        BuildSystem::Cargo => &["rustc", "cargo"],
+        BuildSystem::Cmake => &["cc", "cmake"],
+        BuildSystem::Meson => &["cc", "meson", "ninja"],
+        BuildSystem::Ninja => &["cc", "ninja"],
+        BuildSystem::Nimble => &["nim", "nimble"],
+        BuildSystem::Stack => &["ghc", "stack"],
+        BuildSystem::Cabal => &["ghc", "cabal"],
+        BuildSystem::Pip => &["python3", "pip"],
+        BuildSystem::Custom | BuildSystem::Unknown => &[],
+    }
+}
+
+fn sandbox_image(build_system: BuildSystem, opts: &InstallOptions) -> io::Result<String> {
+    if matches!(build_system, BuildSystem::Pip | BuildSystem::Custom) {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "--sandbox is not supported for this build system"));
+    }
+    if let Some(image) = &opts.sandbox_image {
+        return Ok(image.clone());
+    }
+    match build_system {
+        BuildSystem::Cargo => Ok("docker.io/library/rust:latest".to_string()),
+        BuildSystem::Make | BuildSystem::Autotools => Ok("docker.io/library/gcc:latest".to_string()),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "No default sandbox image for this build system, pass --sandbox-image")),
+    }
+}
+
+fn parse_repo(input: &str) -> io::Result<(String, Option<Source>)> {
+    let input = input.trim();
+    let invalid = || io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Invalid repository '{}': expected user/repo or a repository URL", input),
+    );
+
+    let (host, path) = if let Some(rest) = input.strip_prefix("git@") {
+        let (domain, path) = rest.split_once(':').ok_or_else(invalid)?;
+        (Some(domain), path)
+    } else if let Some(rest) = input.strip_prefix("https://").or_else(|| input.strip_prefix("http://")) {
+        let (domain, path) = rest.split_once('/').ok_or_else(invalid)?;
+        (Some(domain), path)
+    } else {
+        (None, input)
+    };
+
+    let source = match host.map(|h| h.parse::<Source>()) {
+        None => None,
+        Some(Ok(source)) => Some(source),
+        Some(Err(_)) => {
+            let url = input.trim_end_matches('/');
+            if url.rsplit(['/', ':']).next().is_none_or(|name| name.is_empty() || name == ".git") {
+                return Err(invalid());
+            }
+            return Ok((url.to_string(), Some(Source::Url)));
+        }
+    };
+
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let segments: Vec<&str> = path.split('/').collect();
+    if segments.len() < 2 || segments.iter().any(|s| s.is_empty()) {
+        return Err(invalid());
+    }
+    if !source.unwrap_or_default().allows_subgroups() && segments.len() != 2 {
+        return Err(invalid());
+    }
+    Ok((segments.join("/"), source))
+}
+
+const RAW_HOSTS: [&str; 2] = ["raw.githubusercontent.com", "gist.githubusercontent.com"];
+
+fn script_url(input: &str, script: bool) -> Option<String> {
+    let input = input.trim();
+    let rest = input.strip_prefix("https://").or_else(|| input.strip_prefix("http://"))?;
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    if host == "gist.github.com" {
+        return Some(format!("https://gist.githubusercontent.com/{}/raw", path.trim_matches('/')));
+    }
+    let extension = path.split(['?', '#']).next().unwrap_or(path).rsplit_once('.').map(|(_, ext)| ext);
+    let explicit = script || RAW_HOSTS.contains(&host) || extension.is_some_and(|ext| SCRIPT_EXTENSIONS.contains(&ext));
+    explicit.then(|| input.to_string())
+}
+
+fn script_name(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let segment = path.trim_end_matches('/').rsplit('/').find(|s| !s.is_empty() && *s != "raw")?;
+    let name = match segment.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && SCRIPT_EXTENSIONS.contains(&ext) => stem,
+        _ => segment,
+    };
+    (!name.contains(':')).then(|| name.to_string())
+}
+
+const SCRIPT_EXTENSIONS: [&str; 6] = ["sh", "bash", "py", "pl", "rb", "js"];
+
+fn install_script(url: &str, tmp: &Path, opts: &InstallOptions) -> io::Result<Outcome> {
+    let start = Instant::now();
+    let name = match opts.rename.clone().or_else(|| script_name(url)) {
+        Some(name) if !name.is_empty() && !name.contains('/') => name,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Can't derive a name from {}, pass --rename", url))),
+    };
+    if opts.offline {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Can't download {} with --offline", url)));
+    }
+    fs::create_dir_all(tmp)?;
+    let download = tmp.join(&name);
+    println!("{}", color::bold(format!("~> Downloading script: {}", url)));
+    archive::download(url, &download)?;
+    let content = fs::read(&download)?;
+    if !content.starts_with(b"#!") && !content.starts_with(b"\x7fELF") {
+        let _ = fs::remove_file(&download);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a script (no #! line) or an ELF binary", url)));
+    }
+    let hash = format!("{:x}", Sha256::digest(&content));
+    if opts.check_only {
+        let _ = fs::remove_file(&download);
+        println!("{}", color::paint(Green, "~> Pre-flight check passed"));
+        return Ok(Outcome::Skipped);
+    }
+
+    let install_location = get_install_path(opts.local, opts.prefix.as_deref());
+    if !install_location.bin_path.exists() {
+        run_command("mkdir", &["-p", install_location.bin_path.to_str().unwrap()], install_location.elevate, None, None)?;
+    }
+    check_conflicts(&[install_location.bin_path.join(&name)], &name, opts)?;
+    let mut registry = Registry::load()?;
+
+    println!("~> Installing...");
+    let installed = copy_binary(&download, &install_location, &name)?;
+    let _ = fs::remove_file(&download);
+    let mode = opts.mode.clone().unwrap_or_else(|| "755".to_string());
+    let permissions = InstallOptions { mode: Some(mode), ..opts.clone() };
+    if let Err(e) = apply_permissions(&[&installed], &permissions, install_location.elevate) {
+        remove_installed(std::slice::from_ref(&installed), install_location.elevate);
+        return Err(e);
+    }
+
+    if !opts.no_record {
+        let pinned = registry.get_scoped(&name, opts.local && opts.prefix.is_none()).is_some_and(|p| p.pinned);
+        registry.upsert(InstalledPackage {
+            name: name.clone(),
+            repo: Some(url.to_string()),
+            branch: None,
+            source: Some(Source::Url),
+            build_system: "Script".to_string(),
+            location: installed.to_string_lossy().to_string(),
+            build_file: None,
+            hash: Some(hash.clone()),
+            version: None,
+            last_commit_hash: Some(hash.clone()),
+            install_date: Some(Local::now().format("%y-%m-%d").to_string()),
+            last_commit_date: Some(Local::now().format("%y-%m-%d").to_string()),
+            stow: None,
+            toolchain: Default::default(),
+            build_cmd: None,
+            install_cmd: None,
+            files: utils::relative_paths(std::slice::from_ref(&installed), &utils::install_prefix(opts.local, opts.prefix.as_deref())),
+            group: opts.group.clone(),
+            mode: opts.mode.clone(),
+            owner: opts.owner.clone(),
+            prefix: opts.prefix.as_ref().map(|p| p.to_string_lossy().to_string()),
+            pinned,
+            build_duration: Some(start.elapsed().as_secs()),
+            target: opts.target.clone(),
+            cmake_toolchain: opts.cmake_toolchain.as_ref().map(|p| p.to_string_lossy().to_string()),
+            binary_hash: Some(hash),
+            mirror: None,
+            cloned_from: Some(url.to_string()),
+            local: opts.local && opts.prefix.is_none(),
+            dirty: false,
+            patch_from: Vec::new(),
+            docdir: None,
+            no_docs: false,
+            bindir: None,
+            libdir: None,
+            includedir: None,
+            extras: false,
+            flags: Vec::new(),
+            no_default_flags: false,
+            user_flags_first: false,
+            build_target: None,
+            install_target: None,
+            patches: None,
+            patch_urls: Vec::new(),
+            rustflags: None,
+            codegen_units: None,
+        });
+        registry.save()?;
+    }
+
+    println!("{} in {}s", color::paint(Green, "~> INSTALL FINISHED"), start.elapsed().as_secs());
+    warn_if_shadowed(&install_location.bin_path, &name);
+    Ok(Outcome::Installed)
+}
+
+fn build_to_output_dir(repo: &str, output_dir: &Path, staging: &Path, opts: &InstallOptions) -> io::Result<()> {
+    if opts.stow || opts.local || opts.prefix.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--output-dir can't be combined with --stow, --local or --prefix"));
+    }
+    let _ = fs::remove_dir_all(staging);
+    let staged = InstallOptions { output_dir: None, prefix: Some(staging.to_path_buf()), no_record: true, ..opts.clone() };
+    let result = install(repo, &staged).and_then(|outcome| {
+        if outcome == Outcome::Skipped || !staging.exists() {
+            return Ok(());
+        }
+        fs::create_dir_all(output_dir)?;
+        for entry in fs::read_dir(staging)?.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            let source = if entry.file_name() == "bin" { path.join(".") } else { path };
+            let status = Command::new("cp").arg("-a").arg(&source).arg(output_dir).status()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("Failed to copy {} to {}: cp {}", source.display(), output_dir.display(), status)));
+            }
+        }
+        println!("{}", color::paint(Green, format!("~> Artifacts copied to {}", output_dir.display())));
+        Ok(())
+    });
+    let _ = fs::remove_dir_all(staging);
+    result
+}
+
+fn check_conflicts(dests: &[PathBuf], name: &str, opts: &InstallOptions) -> io::Result<()> {
+    let registry = Registry::load()?;
+    let local = opts.local && opts.prefix.is_none();
+    let mut conflicts = 0;
+    for dest in dests {
+        if let Some(owner) = registry.owner_of(dest, name, local) {
+            println!("{}", color::paint(Yellow, format!("Warning: {} is already owned by package {}", dest.display(), owner.name)));
+            conflicts += 1;
+        }
+    }
+    if conflicts == 0 || confirm(opts, Guard::Destructive, "Overwrite files owned by another package?")? {
+        return Ok(());
+    }
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("Install cancelled, {} file(s) belong to another package", conflicts)))
+}
+
+fn crate_name(input: &str, source: Option<Source>) -> Option<String> {
+    let input = input.trim();
+    let name = match input.strip_prefix("https://crates.io/crates/").or_else(|| input.strip_prefix("crates.io/crates/")) {
+        Some(rest) => rest.split(['/', '?', '#']).next().unwrap_or(rest),
+        None if source == Some(Source::Crates) => input,
+        None => return None,
+    };
+    Some(name.to_string())
+}
+
+fn crate_binaries(root: &Path) -> io::Result<(String, Vec<String>)> {
+    let path = root.join(".crates2.json");
+    let content = fs::read_to_string(&path)?;
+    let value: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+    let (key, install) = value["installs"]
+        .as_object()
+        .and_then(|installs| installs.iter().next())
+        .ok_or_else(|| io::Error::other(format!("{} lists no installed crate", path.display())))?;
+    let version = key.split(' ').nth(1).unwrap_or_default().to_string();
+    let bins = install["bins"]
+        .as_array()
+        .map(|bins| bins.iter().filter_map(|b| b.as_str().map(String::from)).collect())
+        .unwrap_or_default();
+    Ok((version, bins))
+}
+
+fn install_crate(crate_name: &str, tmp: &Path, opts: &InstallOptions) -> io::Result<Outcome> {
+    let start = Instant::now();
+    let (krate, requested) = match crate_name.split_once('@') {
+        Some((krate, version)) => (krate, Some(version)),
+        None => (crate_name, None),
+    };
+    let name = opts.rename.as_deref().unwrap_or(krate);
+    if krate.is_empty() || name.is_empty() || name.contains('/') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid crate name: '{}'", crate_name)));
+    }
+    if !utils::check_dependency("cargo") {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "cargo is required to install crates"));
+    }
+    if opts.check_only {
+        println!("{}", color::paint(Green, "~> Pre-flight check passed"));
+        return Ok(Outcome::Skipped);
+    }
+
+    let root = tmp.join(krate);
+    if root.exists() {
+        fs::remove_dir_all(&root)?;
+    }
+    fs::create_dir_all(&root)?;
+    println!("{}", color::bold(format!("~> Installing crate {} from crates.io", crate_name)));
+    let jobs = job_count(opts).map(|j| j.to_string());
+    let mut args = vec!["install", krate, "--root", root.to_str().unwrap(), "--force", "--locked"];
+    if let Some(version) = requested {
+        args.extend(["--version", version]);
+    }
+    if let Some(jobs) = &jobs {
+        args.extend(["-j", jobs.as_str()]);
+    }
+    if let Some(target) = &opts.target {
+        args.extend(["--target", target.as_str()]);
+    }
+    if opts.offline {
+        args.push("--offline");
+    }
+    run_command("cargo", &args, false, None, None)?;
+    let (version, bins) = crate_binaries(&root)?;
+    if bins.is_empty() {
+        return Err(io::Error::other(format!("Crate {} has no binaries to install", krate)));
+    }
+
+    let install_location = get_install_path(opts.local, opts.prefix.as_deref());
+    if !install_location.bin_path.exists() {
+        run_command("mkdir", &["-p", install_location.bin_path.to_str().unwrap()], install_location.elevate, None, None)?;
+    }
+    let original = if bins.len() == 1 { bins[0].as_str() } else { krate };
+    let names: Vec<&str> = bins.iter().map(|bin| renamed(bin, original, name)).collect();
+    let dests: Vec<PathBuf> = names.iter().map(|bin| install_location.bin_path.join(bin)).collect();
+    check_conflicts(&dests, name, opts)?;
+    let mut registry = Registry::load()?;
+
+    println!("~> Installing...");
+    let mut files = Vec::new();
+    for (bin, dest) in bins.iter().zip(&names) {
+        match copy_binary(&root.join("bin").join(bin), &install_location, dest) {
+            Ok(installed) => files.push(installed),
+            Err(e) => {
+                remove_installed(&files, install_location.elevate);
+                return Err(e);
+            }
+        }
+    }
+    let _ = fs::remove_dir_all(&root);
+    if name != krate && let Err(e) = rename_binary(&mut files, &install_location, krate, name, opts) {
+        remove_installed(&files, install_location.elevate);
+        return Err(e);
+    }
+    let installed: Vec<&PathBuf> = files.iter().collect();
+    if let Err(e) = apply_permissions(&installed, opts, install_location.elevate) {
+        remove_installed(&files, install_location.elevate);
+        return Err(e);
+    }
+    println!("~> Installed {} {}", krate, version);
+
+    if !opts.no_record {
+        let pinned = registry.get_scoped(name, opts.local && opts.prefix.is_none()).is_some_and(|p| p.pinned);
+        let main = files.iter().find(|f| f.file_name().is_some_and(|f| f == name)).unwrap_or(&files[0]);
+        registry.upsert(InstalledPackage {
+            name: name.to_string(),
+            repo: Some(krate.to_string()),
+            branch: None,
+            source: Some(Source::Crates),
+            build_system: "Cargo".to_string(),
+            location: main.to_string_lossy().to_string(),
+            build_file: Some("Cargo.toml".to_string()),
+            hash: Some(version.clone()),
+            version: Some(version.clone()),
+            last_commit_hash: Some(version),
+            install_date: Some(Local::now().format("%y-%m-%d").to_string()),
+            last_commit_date: Some(Local::now().format("%y-%m-%d").to_string()),
+            stow: None,
+            toolchain: utils::collect_toolchain(toolchain_tools(BuildSystem::Cargo)),
+            build_cmd: None,
+            install_cmd: None,
+            files: utils::relative_paths(&files, &utils::install_prefix(opts.local, opts.prefix.as_deref())),
+            group: opts.group.clone(),
+            mode: opts.mode.clone(),
+            owner: opts.owner.clone(),
+            prefix: opts.prefix.as_ref().map(|p| p.to_string_lossy().to_string()),
+            pinned,
+            build_duration: Some(start.elapsed().as_secs()),
+            target: opts.target.clone(),
+            cmake_toolchain: None,
+            binary_hash: utils::file_sha256(main).ok(),
+            mirror: None,
+            cloned_from: Some(Source::Crates.url(krate)),
+            local: opts.local && opts.prefix.is_none(),
+            dirty: false,
+            patch_from: Vec::new(),
+            docdir: None,
+            no_docs: false,
+            bindir: None,
+            libdir: None,
+            includedir: None,
+            extras: false,
+            flags: Vec::new(),
+            no_default_flags: false,
+            user_flags_first: false,
+            build_target: None,
+            install_target: None,
+            patches: None,
+            patch_urls: Vec::new(),
+            rustflags: None,
+            codegen_units: None,
+        });
+        registry.save()?;
+    }
+
+    println!("{} in {}s", color::paint(Green, "~> INSTALL FINISHED"), start.elapsed().as_secs());
+    for file in &files {
+        warn_if_shadowed(&install_location.bin_path, &file.file_name().unwrap().to_string_lossy());
+    }
+    Ok(Outcome::Installed)
+}
+
+const MAKEFILES: [&str; 4] = ["BSDMakefile", "GNUmakefile", "Makefile", "makefile"];
+
+const PREFERENCE_NAMES: [&str; 11] = ["charoite", "make", "autotools", "cargo", "cmake", "meson", "ninja", "nimble", "stack", "cabal", "pip"];
+
+fn preference_name(build_system: BuildSystem) -> String {
+    match build_system {
+        BuildSystem::Unknown => "charoite".to_string(),
+        other => format!("{:?}", other).to_lowercase(),
+    }
+}
+
+fn validate_prefer(prefer: &[String]) -> io::Result<()> {
+    match prefer.iter().find(|p| !PREFERENCE_NAMES.contains(&p.as_str())) {
+        Some(name) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown build system '{}' in preference list (expected one of {})", name, PREFERENCE_NAMES.join(", ")),
+        )),
+        None => Ok(()),
+    }
+}
+
+type Detected = (BuildSystem, Option<String>, Vec<String>, Vec<String>);
+
+fn detect_build_system(prefer: &[String], forced: Option<&str>, assume_first: bool) -> io::Result<Detected> {
+    if let Some(file) = forced {
+        let build_system = build_system_for_file(file)?;
+        println!("~> Using build file {}", file);
+        return detected_from(file, build_system);
+    }
+    let cabal_file = fs::read_dir(".")
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .find(|f| Path::new(f).extension().is_some_and(|e| e == "cabal"));
+    let mut build_files = Vec::new();
+    if Path::new("radon.json").exists() {
+        build_files.push(("radon.json", BuildSystem::Unknown));
+    }
+    if Path::new("charoite.json").exists() {
+        build_files.push(("charoite.json", BuildSystem::Unknown));
+    }
+    if Path::new("Cargo.toml").exists() {
+        build_files.push(("Cargo.toml", BuildSystem::Cargo));
+    }
+    if let Some(makefile) = MAKEFILES.into_iter().find(|f| Path::new(f).exists()) {
+        build_files.push((makefile, BuildSystem::Make));
+    }
+    if Path::new("configure").exists() {
+        build_files.push(("configure", BuildSystem::Autotools));
+    } else if let Some(ac) = ["configure.ac", "configure.in"].into_iter().find(|f| Path::new(f).exists()) {
+        build_files.push((ac, BuildSystem::Autotools));
+    }
+    if Path::new("CMakeLists.txt").exists() {
+        build_files.push(("CMakeLists.txt", BuildSystem::Cmake));
+    }
+    if Path::new("meson.build").exists() {
+        build_files.push(("meson.build", BuildSystem::Meson));
+    }
+    if Path::new("build.ninja").exists() {
+        build_files.push(("build.ninja", BuildSystem::Ninja));
+    }
+    if fs::read_dir(".")
+        .unwrap()
+        .any(|e| e.unwrap().path().extension().map(|e| e == "nimble").unwrap_or(false)) 
+    {
+        build_files.push(("*.nimble", BuildSystem::Nimble));
+    }
+    if Path::new("stack.yaml").exists() {
+        build_files.push(("stack.yaml", BuildSystem::Stack));
+    } else if let Some(cabal) = &cabal_file {
+        build_files.push((cabal.as_str(), BuildSystem::Cabal));
+    }
+    if Path::new("requirements.txt").exists() {
+        build_files.push(("requirements.txt", BuildSystem::Pip));
+    }
+    let preferred = prefer.iter().find_map(|name| {
+        build_files.iter().find(|(_, system)| preference_name(*system) == *name).copied()
+    });
+    let (build_file, build_system) = if !build_files.is_empty() {
+        if build_files.len() > 1 && let Some(found) = preferred {
+            println!("~> Multiple build files detected, preferring {}", found.0);
+            found
+        } else if build_files.len() > 1 && assume_first {
+            println!("~> Multiple build files detected, using {} (pass --prefer to choose another)", build_files[0].0);
+            build_files[0]
+        } else if build_files.len() > 1 && !io::stdin().is_terminal() {
+            eprintln!("Multiple build files detected; pass --prefer to choose one non-interactively");
+            return Ok((BuildSystem::Unknown, None, vec![], vec![]));
+        } else if build_files.len() > 1 {
+            println!("{}", color::paint(Cyan.bold(), "Multiple build files detected. Select one:"));
+            for (i, (file, _)) in build_files.iter().enumerate() {
+                println!("{}: {}", i + 1, file);
+            }
+            io::stdout().flush().unwrap();
+            let mut input = String::new();
+            io::stdin().read_line(&mut input).unwrap();
+            let choice: usize = input.trim().parse().unwrap_or(0);
+            if choice > 0 && choice <= build_files.len() {
+                build_files[choice - 1]
+            } else {
+                return Ok((BuildSystem::Unknown, None, vec![], vec![]));
+            }
+        } else {
+            build_files[0]
+        }
+    } else {
+        return Ok((BuildSystem::Unknown, None, vec![], vec![]));
+    };
+    detected_from(build_file, build_system)
+}
+
+fn build_system_for_file(file: &str) -> io::Result<BuildSystem> {
+    if file.contains('/') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid --build-file '{}': expected a file in the repository root", file)));
+    }
+    if !Path::new(file).is_file() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("Build file {} not found in the repository", file)));
+    }
+    let extension = Path::new(file).extension().and_then(|e| e.to_str());
+    match file {
+        "radon.json" | "charoite.json" => Ok(BuildSystem::Unknown),
+        "Cargo.toml" => Ok(BuildSystem::Cargo),
+        _ if MAKEFILES.contains(&file) => Ok(BuildSystem::Make),
+        "configure" | "configure.ac" | "configure.in" => Ok(BuildSystem::Autotools),
+        "CMakeLists.txt" => Ok(BuildSystem::Cmake),
+        "meson.build" => Ok(BuildSystem::Meson),
+        "build.ninja" => Ok(BuildSystem::Ninja),
+        _ if extension == Some("nimble") => Ok(BuildSystem::Nimble),
+        "stack.yaml" => Ok(BuildSystem::Stack),
+        _ if extension == Some("cabal") => Ok(BuildSystem::Cabal),
+        "requirements.txt" => Ok(BuildSystem::Pip),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Don't know which build system uses {}", file))),
+    }
+}
+
+fn detected_from(build_file: &str, build_system: BuildSystem) -> io::Result<Detected> {
+    let (deps, flags) = match build_system {
+        BuildSystem::Make => (parse_make_deps(Path::new(".")), vec![]),
+        BuildSystem::Autotools => (parse_autotools_deps(Path::new(".")), vec![]),
+        BuildSystem::Cargo => (parse_cargo_deps(Path::new(".")), vec![]),
+        BuildSystem::Cmake => (vec!["cmake".to_string()], vec![]),
+        BuildSystem::Meson => (vec!["meson".to_string(), "ninja".to_string()], vec![]),
+        BuildSystem::Ninja => (vec!["ninja".to_string()], vec![]),
+        BuildSystem::Nimble => (vec!["nim".to_string(), "nimble".to_string()], vec![]),
+        BuildSystem::Stack => (vec!["stack".to_string()], vec![]),
+        BuildSystem::Cabal => (vec!["cabal".to_string(), "ghc".to_string()], vec![]),
+        BuildSystem::Pip => (vec!["pip".to_string()], vec![]),
+        _ => (vec![], vec![]),
+    };
+    let (build_system, mut deps, flags) = if build_file == "radon.json" || build_file == "charoite.json" {
+        parse_charoite_json(Path::new(build_file))?
+    } else {
+        (build_system, deps, flags)
+    };
+    for dep in parse_deps_file(Path::new(".charoite-deps")) {
+        if !deps.contains(&dep) {
+            deps.push(dep);
+        }
+    }
+    Ok((build_system, Some(build_file.to_string()), deps, flags))
+}
+
+fn parse_deps_file(path: &Path) -> Vec<String> {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    content
+        .lines()
+        .map(|l| l.split('#').next().unwrap().trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect()
+}
+
+fn parse_charoite_json(path: &Path) -> io::Result<(BuildSystem, Vec<String>, Vec<String>)> {
+    let content = fs::read_to_string(path)?;
+    let invalid = |e: serde_json::Error| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {}: {}", path.display(), e));
+    let mut manifest: Manifest = serde_json::from_str(&content).map_err(invalid)?;
+    let mut sections: HashMap<String, serde_json::Value> = serde_json::from_str(&content).map_err(invalid)?;
+    let platform = format!("{}-{}", env::consts::OS, env::consts::ARCH);
+    for key in [env::consts::OS, env::consts::ARCH, platform.as_str()] {
+        let Some(section) = sections.remove(key) else { continue };
+        let section: ManifestOverride = serde_json::from_value(section)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {} section \"{}\": {}", path.display(), key, e)))?;
+        println!("~> Applying {} overrides from {}", key, path.display());
+        if let Some(build_system) = section.build_system {
+            manifest.build_system = build_system;
+        }
+        if let Some(dependencies) = section.dependencies {
+            manifest.dependencies = dependencies;
+        }
+        if let Some(flags) = section.flags {
+            manifest.flags = flags;
+        }
+    }
+    Ok((manifest.build_system, manifest.dependencies, manifest.flags))
+}
+
+fn parse_make_deps(dir: &Path) -> Vec<String> {
+    let found_file = MAKEFILES.iter().find(|f| dir.join(f).exists()).unwrap_or(&"Makefile");
+    let makefile = fs::read_to_string(dir.join(found_file)).unwrap_or_default();
+    makefile.lines().find(|l| l.contains("# DEPENDENCIES:")).map(|l| {
+        l.split(':').nth(1).unwrap().split(',').map(|s| s.trim().to_string()).collect()
+    }).unwrap_or_default()
+}
+
+fn parse_autotools_deps(dir: &Path) -> Vec<String> {
+    let mut deps = Vec::new();
+    let configure = match fs::read_to_string(dir.join("configure")) {
+        Ok(configure) => configure,
+        Err(_) => {
+            deps.extend(["autoconf", "automake", "libtool"].map(String::from));
+            ["configure.ac", "configure.in"]
+                .iter()
+                .find_map(|f| fs::read_to_string(dir.join(f)).ok())
+                .unwrap_or_default()
+        }
+    };
+    if configure.contains("PKG_CHECK_MODULES") {
+        deps.push("pkg-config".to_string());
+    } else {
+        println!("{}", color::paint(Yellow, "Warning: Autotools project doesn't use pkg-config"));
+    }
+    if configure.contains("AC_PROG_CC") {
+        deps.push("gcc".to_string());
+    }
+    if configure.contains("AC_PROG_CXX") {
         deps.push("g++".to_string());
     }
     deps
 }
 
 fn parse_cargo_deps(dir: &Path) -> Vec<String> {
-    let cargo_toml = fs::read_to_string(dir.join("Cargo.toml")).unwrap_or_default();
-    let value = cargo_toml.parse::<serde_json::Value>().unwrap_or(serde_json::Value::Null);
-    value.get("package").and_then(|p| p.get("metadata")).and_then(|m| m.get("charoite")).and_then(|r| r.get("dependencies")).and_then(|d| d.as_array()).map(|deps| {
+    let Some(manifest) = read_cargo_manifest(dir) else { return Vec::new() };
+    if !is_virtual_workspace(&manifest) {
+        return charoite_metadata_deps(manifest.get("package"));
+    }
+    let mut deps = charoite_metadata_deps(manifest.get("workspace"));
+    for member in cargo_workspace_members(dir, &manifest) {
+        let Some(member) = read_cargo_manifest(&member) else { continue };
+        for dep in charoite_metadata_deps(member.get("package")) {
+            if !deps.contains(&dep) {
+                deps.push(dep);
+            }
+        }
+    }
+    deps
+}
+
+fn charoite_metadata_deps(section: Option<&toml::Value>) -> Vec<String> {
+    section.and_then(|p| p.get("metadata")).and_then(|m| m.get("charoite")).and_then(|r| r.get("dependencies")).and_then(|d| d.as_array()).map(|deps| {
         deps.iter().filter_map(|d| d.as_str().map(|s| s.to_string())).collect()
     }).unwrap_or_default()
 }
 
+fn read_cargo_manifest(dir: &Path) -> Option<toml::Table> {
+    fs::read_to_string(dir.join("Cargo.toml")).ok()?.parse::<toml::Table>().ok()
+}
+
+fn is_virtual_workspace(manifest: &toml::Table) -> bool {
+    !manifest.contains_key("package") && manifest.contains_key("workspace")
+}
+
+fn cargo_workspace_members(dir: &Path, manifest: &toml::Table) -> Vec<PathBuf> {
+    let workspace = manifest.get("workspace");
+    let list = |key: &str| -> Vec<String> {
+        workspace
+            .and_then(|w| w.get(key))
+            .and_then(|m| m.as_array())
+            .map(|m| m.iter().filter_map(|v| v.as_str().map(|s| s.trim_end_matches('/').to_string())).collect())
+            .unwrap_or_default()
+    };
+    let excluded: Vec<PathBuf> = list("exclude").iter().map(|e| dir.join(e)).collect();
+    let mut members = Vec::new();
+    for pattern in list("members") {
+        match pattern.strip_suffix("/*") {
+            Some(parent) => {
+                let mut found: Vec<PathBuf> = fs::read_dir(dir.join(parent))
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| p.join("Cargo.toml").is_file())
+                    .collect();
+                found.sort();
+                members.extend(found);
+            }
+            None => members.push(dir.join(pattern)),
+        }
+    }
+    members.retain(|m| !excluded.contains(m));
+    members
+}
+
+fn cargo_version(dir: &Path, repo_name: &str) -> Option<String> {
+    let manifest = read_cargo_manifest(dir)?;
+    let workspace_version = manifest.get("workspace").and_then(|w| w.get("package")).and_then(|p| p.get("version")).and_then(|v| v.as_str());
+    let package_version = |package: &toml::Value| -> Option<String> {
+        match package.get("version")? {
+            toml::Value::String(v) => Some(v.clone()),
+            inherited if inherited.get("workspace").and_then(|w| w.as_bool()) == Some(true) => workspace_version.map(|v| v.to_string()),
+            _ => None,
+        }
+    };
+    if !is_virtual_workspace(&manifest) {
+        return package_version(manifest.get("package")?);
+    }
+    cargo_workspace_members(dir, &manifest)
+        .iter()
+        .filter_map(|member| read_cargo_manifest(member)?.remove("package"))
+        .find(|package| package.get("name").and_then(|n| n.as_str()) == Some(repo_name))
+        .and_then(|package| package_version(&package))
+        .or_else(|| workspace_version.map(|v| v.to_string()))
+}
+
 fn check_pkg_config_usage(build_system: BuildSystem, build_file: Option<&String>) -> bool {
     match build_system {
         BuildSystem::Autotools => true,
@@ -408,26 +1908,58 @@ fn check_pkg_config_usage(build_system: BuildSystem, build_file: Option<&String>
             }
             false
         }
+        BuildSystem::Cabal => {
+            if let Some(file) = build_file {
+                let cabal = fs::read_to_string(file).unwrap_or_default();
+                if cabal.contains("pkgconfig-depends") {
+                    return true;
+                }
+            }
+            false
+        }
         _ => false,
     }
 }
 
-fn get_install_path(local: bool) -> InstallLocation {
-    if local {
-        let home = env::var("HOME").unwrap();
-        let local_bin = PathBuf::from(home).join(".local/bin");
-        if !local_bin.exists() {
-            fs::create_dir_all(&local_bin).expect("Failed to create local bin directory");
-        }
-        InstallLocation { bin_path: local_bin, elevate: false }
+pub fn get_install_path(local: bool, prefix: Option<&Path>) -> InstallLocation {
+    if let Some(prefix) = prefix {
+        InstallLocation { bin_path: prefix.join("bin"), elevate: needs_elevation(prefix) }
+    } else if local {
+        InstallLocation { bin_path: utils::install_prefix(true, None).join("bin"), elevate: false }
     } else {
-        InstallLocation { bin_path: PathBuf::from("/usr/local/bin"), elevate: true }
+        InstallLocation { bin_path: utils::install_prefix(false, None).join("bin"), elevate: true }
     }
 }
 
-fn run_command(cmd: &str, args: &[&str], elevate: bool, current_dir: Option<&Path>) -> io::Result<()> {
+fn needs_elevation(dir: &Path) -> bool {
+    let existing = dir.ancestors().find(|p| p.exists()).unwrap_or(Path::new("/"));
+    !utils::has_write_access(existing)
+}
+
+fn install_dirs(opts: &InstallOptions) -> Vec<&Path> {
+    [&opts.docdir, &opts.bindir, &opts.libdir, &opts.includedir].into_iter().flatten().map(PathBuf::as_path).collect()
+}
+
+fn install_dir_args(build_system: BuildSystem, opts: &InstallOptions) -> Vec<String> {
+    let dirs = [("bin", &opts.bindir), ("lib", &opts.libdir), ("include", &opts.includedir)];
+    dirs.into_iter()
+        .filter_map(|(kind, dir)| {
+            let dir = dir.as_ref()?.display();
+            match build_system {
+                BuildSystem::Autotools | BuildSystem::Meson => Some(format!("--{}dir={}", kind, dir)),
+                BuildSystem::Cmake => Some(format!("-DCMAKE_INSTALL_{}DIR={}", kind.to_uppercase(), dir)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+const STDERR_TAIL_LINES: usize = 20;
+const STDERR_CAPTURE_BYTES: usize = 64 * 1024;
+
+fn run_command(cmd: &str, args: &[&str], elevate: bool, current_dir: Option<&Path>, log: Option<&Path>) -> io::Result<()> {
     let mut command = if elevate {
-        let mut c = Command::new("sudo");
+        let mut c = Command::new(utils::get_privilege_command());
         c.arg(cmd);
         c.args(args);
         c
@@ -439,74 +1971,505 @@ fn run_command(cmd: &str, args: &[&str], elevate: bool, current_dir: Option<&Pat
     if let Some(dir) = current_dir {
         command.current_dir(dir);
     }
-    command.stdout(Stdio::inherit()).stderr(Stdio::inherit()).status().and_then(|status| {
-        if status.success() {
-            Ok(())
-        } else {
-            Err(io::Error::new(io::ErrorKind::Other, "Command failed"))
+    let log_file = match log {
+        Some(log_path) => {
+            let mut log_file = fs::OpenOptions::new().create(true).append(true).open(log_path)?;
+            writeln!(log_file, "$ {} {}", cmd, args.join(" "))?;
+            Some(Arc::new(Mutex::new(log_file)))
+        }
+        None => None,
+    };
+    let stdout_mode = if log_file.is_some() { Stdio::piped() } else { Stdio::inherit() };
+    let mut child = command.stdout(stdout_mode).stderr(Stdio::piped()).spawn()?;
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let stdout = child.stdout.take().map(|out| tee_output(out, io::stdout(), log_file.clone(), None));
+    let stderr = tee_output(child.stderr.take().unwrap(), io::stderr(), log_file, Some(Arc::clone(&captured)));
+    let status = child.wait()?;
+    if let Some(stdout) = stdout {
+        let _ = stdout.join();
+    }
+    let _ = stderr.join();
+    if status.success() {
+        Ok(())
+    } else {
+        let captured = captured.lock().unwrap();
+        Err(command_error(cmd, status, &String::from_utf8_lossy(&captured)))
+    }
+}
+
+fn command_error(cmd: &str, status: ExitStatus, stderr: &str) -> io::Error {
+    let lines: Vec<&str> = stderr.lines().filter(|l| !l.trim().is_empty()).collect();
+    let tail = lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n");
+    if tail.is_empty() {
+        io::Error::other(format!("{} failed ({})", cmd, status))
+    } else {
+        io::Error::other(format!("{} failed ({}):\n{}", cmd, status, tail))
+    }
+}
+
+fn run_build_command(cmd: &str, args: &[&str], work_dir: &Path, mount: &Path, log: Option<&Path>, opts: &InstallOptions) -> io::Result<()> {
+    match (opts.sandbox, &opts.sandbox_image) {
+        (Some(sandbox), Some(image)) => {
+            let stage = Path::new(STAGE_DIR).join(mount.file_name().unwrap());
+            let mut mounts = vec![mount];
+            if stage.exists() {
+                mounts.push(&stage);
+            }
+            let wrapped = sandbox.wrap(image, &mounts, work_dir, cmd, args);
+            let wrapped: Vec<&str> = wrapped.iter().map(|s| s.as_str()).collect();
+            run_command(sandbox.engine(), &wrapped, false, Some(work_dir), log)
+        }
+        _ => run_command(cmd, args, false, Some(work_dir), log),
+    }
+}
+
+fn tee_output<R, W>(mut reader: R, mut terminal: W, log: Option<Arc<Mutex<fs::File>>>, capture: Option<Arc<Mutex<Vec<u8>>>>) -> thread::JoinHandle<()>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        while let Ok(n) = reader.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            let _ = terminal.write_all(&buf[..n]);
+            let _ = terminal.flush();
+            if let Some(log) = &log {
+                let _ = log.lock().unwrap().write_all(&buf[..n]);
+            }
+            if let Some(capture) = &capture {
+                let mut capture = capture.lock().unwrap();
+                capture.extend_from_slice(&buf[..n]);
+                if capture.len() > STDERR_CAPTURE_BYTES {
+                    let excess = capture.len() - STDERR_CAPTURE_BYTES;
+                    capture.drain(..excess);
+                }
+            }
+        }
+    })
+}
+
+fn apply_patches(build_dir: &Path, patches_dir: &Path) {
+    let patches: Vec<PathBuf> = fs::read_dir(patches_dir).unwrap().filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.extension().map(|e| e == "patch").unwrap_or(false)).collect();
+    for patch in patches {
+        apply_patch(build_dir, &patch);
+    }
+}
+
+fn apply_patch(build_dir: &Path, patch: &Path) {
+    println!("Applying patch: {}", patch.display());
+    let status = Command::new("patch")
+        .arg("-Np1")
+        .arg("--directory")
+        .arg(build_dir)
+        .arg("--input")
+        .arg(patch)
+        .status()
+        .expect("Failed to apply patch");
+    if !status.success() {
+        eprintln!("{}: Failed to apply {}", color::paint(Red, "Error"), patch.display());
+    }
+}
+
+fn fetch_patches(urls: &[String], dest_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if dest_dir.exists() {
+        fs::remove_dir_all(dest_dir)?;
+    }
+    fs::create_dir_all(dest_dir)?;
+    let mut patches = Vec::new();
+    for (i, url) in urls.iter().enumerate() {
+        println!("~> Downloading patch: {}", url);
+        let path = dest_dir.join(format!("{:02}.patch", i + 1));
+        archive::download(url, &path)?;
+        let content = fs::read_to_string(&path).unwrap_or_default();
+        if !looks_like_patch(&content) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} does not look like a patch", url)));
         }
-    })
+        patches.push(path);
+    }
+    Ok(patches)
 }
 
-fn apply_patches(build_dir: &Path, patches_dir: &Path) {
-    let patches: Vec<PathBuf> = fs::read_dir(patches_dir).unwrap().filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.extension().map(|e| e == "patch").unwrap_or(false)).collect();
-    for patch in patches {
-        println!("Applying patch: {}", patch.display());
-        let status = Command::new("patch")
-            .arg("-Np1")
-            .arg("--directory")
-            .arg(build_dir)
-            .arg("--input")
-            .arg(&patch)
-            .status()
-            .expect("Failed to apply patch");
-        if !status.success() {
-            eprintln!("{}: Failed to apply {}", Red.paint("Error"), patch.display());
-        }
+fn looks_like_patch(content: &str) -> bool {
+    let has = |prefix: &str| content.lines().any(|l| l.starts_with(prefix));
+    has("--- ") && has("+++ ") && has("@@ ")
+}
+
+fn cmake_toolchain_arg(opts: &InstallOptions) -> Option<String> {
+    opts.cmake_toolchain.as_ref().map(|file| format!("-DCMAKE_TOOLCHAIN_FILE={}", file.display()))
+}
+
+fn read_cmake_presets(build_dir: &Path) -> Vec<serde_json::Value> {
+    ["CMakePresets.json", "CMakeUserPresets.json"]
+        .iter()
+        .filter_map(|f| fs::read_to_string(build_dir.join(f)).ok())
+        .filter_map(|c| serde_json::from_str(&c).ok())
+        .collect()
+}
+
+fn cmake_presets(build_dir: &Path) -> Vec<(String, Option<String>)> {
+    let files = read_cmake_presets(build_dir);
+    let all: Vec<&serde_json::Value> = files
+        .iter()
+        .filter_map(|f| f["configurePresets"].as_array())
+        .flatten()
+        .collect();
+    all.iter()
+        .filter(|p| !p["hidden"].as_bool().unwrap_or(false))
+        .filter_map(|p| p["name"].as_str().map(|name| (name.to_string(), preset_binary_dir(&all, p, 0))))
+        .collect()
+}
+
+fn preset_binary_dir(all: &[&serde_json::Value], preset: &serde_json::Value, depth: usize) -> Option<String> {
+    if let Some(dir) = preset["binaryDir"].as_str() {
+        return Some(dir.to_string());
     }
+    if depth > all.len() {
+        return None;
+    }
+    let parents: Vec<&str> = match &preset["inherits"] {
+        serde_json::Value::String(parent) => vec![parent.as_str()],
+        serde_json::Value::Array(parents) => parents.iter().filter_map(|p| p.as_str()).collect(),
+        _ => vec![],
+    };
+    parents.into_iter().find_map(|name| {
+        let parent = all.iter().find(|p| p["name"].as_str() == Some(name))?;
+        preset_binary_dir(all, parent, depth + 1)
+    })
+}
+
+fn has_cmake_build_preset(build_dir: &Path, name: &str) -> bool {
+    read_cmake_presets(build_dir)
+        .iter()
+        .filter_map(|f| f["buildPresets"].as_array())
+        .flatten()
+        .any(|p| p["name"].as_str() == Some(name))
+}
+
+fn cmake_build_path(build_dir: &Path, opts: &InstallOptions) -> io::Result<PathBuf> {
+    let Some(Some(preset)) = &opts.cmake_preset else {
+        return Ok(build_dir.join("build"));
+    };
+    let binary_dir = cmake_presets(build_dir)
+        .into_iter()
+        .find(|(name, _)| name == preset)
+        .and_then(|(_, dir)| dir)
+        .ok_or_else(|| io::Error::other(format!("CMake preset '{}' does not set binaryDir", preset)))?;
+    let source_dir = build_dir.to_string_lossy();
+    let expanded = binary_dir
+        .replace("${sourceDir}", &source_dir)
+        .replace("${sourceParentDir}", &build_dir.parent().unwrap_or(build_dir).to_string_lossy())
+        .replace("${sourceDirName}", &build_dir.file_name().unwrap_or_default().to_string_lossy())
+        .replace("${presetName}", preset);
+    Ok(build_dir.join(expanded))
 }
 
 fn build_project(
     build_system: BuildSystem,
     build_dir: &Path,
     flags: &[String],
-    build_file: Option<&String>,
+    log: &Path,
+    opts: &InstallOptions,
 ) -> io::Result<()> {
     let final_flags: Vec<&str> = flags.iter().map(|s| s.as_str()).collect();
     match build_system {
         BuildSystem::Make => {
-            let makefile = if build_dir.join("BSDMakefile").exists() { "BSDMakefile" } else { "Makefile" };
-            run_command("make", &["-f", makefile, &final_flags.join(" ")], false, Some(build_dir))
+            let makefile = makefile(build_dir, opts);
+            let joined = final_flags.join(" ");
+            let jobs_arg = job_count(opts).map(|j| format!("-j{}", j));
+            let mut args = vec!["-f", makefile];
+            args.extend(jobs_arg.as_deref());
+            args.extend(opts.build_target.as_deref());
+            if !joined.is_empty() {
+                args.push(&joined);
+            }
+            run_build_command(utils::make_program(build_dir), &args, build_dir, build_dir, Some(log), opts)
         }
         BuildSystem::Autotools => {
-            run_command("./configure", &final_flags, false, Some(build_dir))?;
-            run_command("make", &[], false, Some(build_dir))
+            if !build_dir.join("configure").exists() {
+                if build_dir.join("autogen.sh").exists() {
+                    run_build_command("sh", &["./autogen.sh"], build_dir, build_dir, Some(log), opts)?;
+                } else if build_dir.join("bootstrap").exists() {
+                    run_build_command("sh", &["./bootstrap"], build_dir, build_dir, Some(log), opts)?;
+                } else {
+                    run_build_command("autoreconf", &["-i"], build_dir, build_dir, Some(log), opts)?;
+                }
+            }
+            let jobs_arg = format!("-j{}", build_jobs(opts));
+            let mut make_args = vec![jobs_arg.as_str()];
+            make_args.extend(opts.build_target.as_deref());
+            let make = utils::make_program(build_dir);
+            let dir_args = install_dir_args(build_system, opts);
+            let mut configure_args: Vec<&str> = dir_args.iter().map(|a| a.as_str()).collect();
+            configure_args.extend(&final_flags);
+            if opts.out_of_tree {
+                let build_path = build_dir.join("build");
+                fs::create_dir_all(&build_path)?;
+                run_build_command("../configure", &configure_args, &build_path, build_dir, Some(log), opts)?;
+                run_build_command(make, &make_args, &build_path, build_dir, Some(log), opts)
+            } else {
+                run_build_command("./configure", &configure_args, build_dir, build_dir, Some(log), opts)?;
+                run_build_command(make, &make_args, build_dir, build_dir, Some(log), opts)
+            }
         }
         BuildSystem::Cargo => {
-            let mut args = vec!["build", "--release"];
+            let mut env_vars = Vec::new();
+            if let Some(rustflags) = &opts.rustflags {
+                env_vars.push(format!("RUSTFLAGS={}", rustflags));
+            }
+            if let Some(units) = opts.codegen_units {
+                env_vars.push(format!("CARGO_PROFILE_RELEASE_CODEGEN_UNITS={}", units));
+            }
+            let jobs = job_count(opts).map(|j| j.to_string());
+            let mut args: Vec<&str> = env_vars.iter().map(|v| v.as_str()).collect();
+            if !args.is_empty() {
+                args.push("cargo");
+            }
+            args.extend(["build", "--release"]);
+            if read_cargo_manifest(build_dir).is_some_and(|m| is_virtual_workspace(&m)) {
+                println!("~> Cargo.toml is a virtual workspace, building all members");
+                args.push("--workspace");
+            }
+            if let Some(jobs) = &jobs {
+                args.extend(["-j", jobs.as_str()]);
+            }
+            if let Some(target) = &opts.target {
+                args.extend(["--target", target.as_str()]);
+            }
+            if opts.offline {
+                args.push("--offline");
+            }
             args.extend(final_flags.iter());
-            run_command("cargo", &args, false, Some(build_dir))
+            let cmd = if env_vars.is_empty() { "cargo" } else { "env" };
+            run_build_command(cmd, &args, build_dir, build_dir, Some(log), opts)
+        }
+        BuildSystem::Cmake if let Some(Some(preset)) = &opts.cmake_preset => {
+            let mut configure_args = vec!["--preset", preset];
+            let toolchain = cmake_toolchain_arg(opts);
+            configure_args.extend(toolchain.as_deref());
+            let dir_args = install_dir_args(build_system, opts);
+            configure_args.extend(dir_args.iter().map(|a| a.as_str()));
+            run_build_command("cmake", &configure_args, build_dir, build_dir, Some(log), opts)?;
+            let target_args = cmake_target_args(opts);
+            if has_cmake_build_preset(build_dir, preset) {
+                let mut args = vec!["--build", "--preset", preset];
+                args.extend(target_args.iter().map(|a| a.as_str()));
+                run_build_command("cmake", &args, build_dir, build_dir, Some(log), opts)
+            } else {
+                let build_path = cmake_build_path(build_dir, opts)?;
+                let mut args = vec!["--build", build_path.to_str().unwrap()];
+                args.extend(target_args.iter().map(|a| a.as_str()));
+                run_build_command("cmake", &args, build_dir, build_dir, Some(log), opts)
+            }
         }
         BuildSystem::Cmake => {
             let build_path = build_dir.join("build");
             fs::create_dir_all(&build_path)?;
-            run_command("cmake", &["-DCMAKE_BUILD_TYPE=Release", ".."], false, Some(&build_path))?;
-            run_command("cmake", &["--build", "."], false, Some(&build_path))
+            let toolchain = cmake_toolchain_arg(opts);
+            let mut configure_args = vec!["-DCMAKE_BUILD_TYPE=Release"];
+            configure_args.extend(toolchain.as_deref());
+            let dir_args = install_dir_args(build_system, opts);
+            configure_args.extend(dir_args.iter().map(|a| a.as_str()));
+            if let Some(generator) = &opts.cmake_generator {
+                configure_args.extend(["-G", generator.as_str()]);
+            }
+            configure_args.push("..");
+            run_build_command("cmake", &configure_args, &build_path, build_dir, Some(log), opts)?;
+            if opts.cmake_generator.as_deref() == Some("Ninja") {
+                let ninja = ninja_args(opts);
+                let args: Vec<&str> = ninja.iter().map(|a| a.as_str()).collect();
+                run_build_command("ninja", &args, &build_path, build_dir, Some(log), opts)
+            } else {
+                let target_args = cmake_target_args(opts);
+                let mut args = vec!["--build", "."];
+                args.extend(target_args.iter().map(|a| a.as_str()));
+                run_build_command("cmake", &args, &build_path, build_dir, Some(log), opts)
+            }
         }
         BuildSystem::Meson => {
             let build_path = build_dir.join("build");
             fs::create_dir_all(&build_path)?;
-            run_command("meson", &["setup", "build"], false, Some(build_dir))?;
-            run_command("ninja", &["-C", "build"], false, Some(build_dir))
+            let mut args = vec!["setup"];
+            if build_path.join("meson-private").exists() {
+                args.push("--reconfigure");
+            }
+            if opts.offline {
+                args.push("--wrap-mode=nodownload");
+            }
+            let prefix_arg = (opts.local || opts.prefix.is_some())
+                .then(|| format!("--prefix={}", utils::install_prefix(opts.local, opts.prefix.as_deref()).display()));
+            args.extend(prefix_arg.as_deref());
+            let dir_args = install_dir_args(build_system, opts);
+            args.extend(dir_args.iter().map(|a| a.as_str()));
+            args.push("build");
+            run_build_command("meson", &args, build_dir, build_dir, Some(log), opts).map_err(|e| {
+                let pending = pending_meson_wraps(build_dir);
+                if pending.is_empty() {
+                    return e;
+                }
+                io::Error::new(e.kind(), format!("{}\nsubprojects that still need downloading: {}", e, pending.join(", ")))
+            })?;
+            let ninja = ninja_args(opts);
+            let mut args = vec!["-C", "build"];
+            args.extend(ninja.iter().map(|a| a.as_str()));
+            run_build_command("ninja", &args, build_dir, build_dir, Some(log), opts)
+        }
+        BuildSystem::Ninja => {
+            let ninja = ninja_args(opts);
+            let mut args: Vec<&str> = ninja.iter().map(|a| a.as_str()).collect();
+            args.extend(final_flags.iter());
+            run_build_command("ninja", &args, build_dir, build_dir, Some(log), opts)
+        }
+        BuildSystem::Nimble => run_build_command("nimble", &["build", &final_flags.join(" ")], build_dir, build_dir, Some(log), opts),
+        BuildSystem::Stack => run_build_command("stack", &["install", &final_flags.join(" "), "--local-bin-path", "bin"], build_dir, build_dir, Some(log), opts),
+        BuildSystem::Cabal => {
+            let mut args = vec!["build"];
+            args.extend(final_flags.iter());
+            run_build_command("cabal", &args, build_dir, build_dir, Some(log), opts)?;
+            let installdir = format!("--installdir={}", build_dir.join(CABAL_BIN_DIR).display());
+            let mut args = vec!["install", &installdir, "--install-method=copy", "--overwrite-policy=always"];
+            args.extend(final_flags.iter());
+            run_build_command("cabal", &args, build_dir, build_dir, Some(log), opts)
         }
-        BuildSystem::Ninja => run_command("ninja", &final_flags, false, Some(build_dir)),
-        BuildSystem::Nimble => run_command("nimble", &["build", &final_flags.join(" ")], false, Some(build_dir)),
-        BuildSystem::Stack => run_command("stack", &["install", &final_flags.join(" "), "--local-bin-path", "bin"], false, Some(build_dir)),
         BuildSystem::Pip => Ok(()),
+        BuildSystem::Custom => {
+            let build_cmd = opts.build_cmd.as_deref().unwrap_or_default();
+            run_build_command("sh", &["-c", build_cmd], build_dir, build_dir, Some(log), opts)
+        }
         _ => Err(io::Error::new(io::ErrorKind::Unsupported, "Unsupported build system")),
     }
 }
 
+fn build_jobs(opts: &InstallOptions) -> usize {
+    job_count(opts).unwrap_or_else(cpu_count)
+}
+
+fn job_count(opts: &InstallOptions) -> Option<usize> {
+    match opts.jobs? {
+        Jobs::Auto => Some(auto_jobs()),
+        Jobs::Count(n) => Some(n),
+    }
+}
+
+fn cpu_count() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+const JOB_MEMORY_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+fn auto_jobs() -> usize {
+    let cpus = cpu_count();
+    let Some(available) = available_memory() else {
+        println!("~> Using {} parallel jobs ({} cpus, available memory unknown)", cpus, cpus);
+        return cpus;
+    };
+    let jobs = cpus.min((available / JOB_MEMORY_BYTES).max(1) as usize);
+    println!(
+        "~> Using {} parallel jobs ({} cpus, {:.1} GiB available, ~2 GiB per job)",
+        jobs,
+        cpus,
+        available as f64 / (1024.0 * 1024.0 * 1024.0)
+    );
+    jobs
+}
+
+fn available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+fn makefile<'a>(build_dir: &Path, opts: &'a InstallOptions) -> &'a str {
+    match opts.build_file.as_deref() {
+        Some(file) if MAKEFILES.contains(&file) => file,
+        _ => MAKEFILES.into_iter().find(|f| build_dir.join(f).exists()).unwrap_or("Makefile"),
+    }
+}
+
+fn make_dir(build_system: BuildSystem, build_dir: &Path, opts: &InstallOptions) -> PathBuf {
+    if build_system == BuildSystem::Autotools && opts.out_of_tree { build_dir.join("build") } else { build_dir.to_path_buf() }
+}
+
+fn make_install_target(build_system: BuildSystem, build_dir: &Path, opts: &InstallOptions) -> String {
+    if let Some(target) = &opts.install_target {
+        return target.clone();
+    }
+    let dir = make_dir(build_system, build_dir, opts);
+    let has_install_strip = MAKEFILES
+        .iter()
+        .filter_map(|f| fs::read_to_string(dir.join(f)).ok())
+        .any(|content| content.lines().any(|l| l.starts_with("install-strip:") || l.starts_with("install-strip ")));
+    if opts.strip && has_install_strip { "install-strip".to_string() } else { "install".to_string() }
+}
+
+fn strip_binaries(files: &[PathBuf], elevate: bool) {
+    for file in files.iter().filter(|f| is_native_binary(f)) {
+        println!("~> strip {}", file.display());
+        if let Err(e) = run_command("strip", &[file.to_str().unwrap()], elevate, None, None) {
+            println!("{}", color::paint(Yellow, format!("Warning: failed to strip {}: {}", file.display(), e)));
+        }
+    }
+}
+
+fn is_native_binary(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    let read = fs::File::open(path).and_then(|mut f| f.read_exact(&mut magic));
+    read.is_ok() && (magic == *b"\x7fELF" || matches!(u32::from_be_bytes(magic), 0xfeedface | 0xfeedfacf | 0xcefaedfe | 0xcffaedfe))
+}
+
+fn cmake_target_args(opts: &InstallOptions) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(jobs) = job_count(opts) {
+        args.extend(["--parallel".to_string(), jobs.to_string()]);
+    }
+    if let Some(target) = &opts.build_target {
+        args.extend(["--target".to_string(), target.clone()]);
+    }
+    args
+}
+
+fn ninja_args(opts: &InstallOptions) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(jobs) = job_count(opts) {
+        args.extend(["-j".to_string(), jobs.to_string()]);
+    }
+    args.extend(opts.build_target.iter().cloned());
+    args
+}
+
+fn supports_build_target(build_system: BuildSystem) -> bool {
+    matches!(build_system, BuildSystem::Make | BuildSystem::Autotools | BuildSystem::Cmake | BuildSystem::Meson | BuildSystem::Ninja)
+}
+
+fn install_build_target(
+    build_system: BuildSystem,
+    install_location: &InstallLocation,
+    build_dir: &Path,
+    target: &str,
+    repo_name: &str,
+    opts: &InstallOptions,
+) -> io::Result<Vec<PathBuf>> {
+    let output_dir = match build_system {
+        BuildSystem::Cmake => cmake_build_path(build_dir, opts)?,
+        BuildSystem::Meson => build_dir.join("build"),
+        BuildSystem::Autotools if opts.out_of_tree => build_dir.join("build"),
+        _ => build_dir.to_path_buf(),
+    };
+    let name = target.rsplit('/').next().unwrap_or(target);
+    match find_executable_in_dir(&output_dir, name).filter(|p| is_executable(p)) {
+        Some(binary) => {
+            let dest = opts.rename.as_deref().unwrap_or(name);
+            check_conflicts(&[install_location.bin_path.join(dest)], opts.rename.as_deref().unwrap_or(repo_name), opts)?;
+            Ok(vec![copy_binary(&binary, install_location, dest)?])
+        }
+        None => Err(io::Error::new(io::ErrorKind::NotFound, format!("Target {} built no executable named {}", target, name))),
+    }
+}
+
+#[allow(clippy::collapsible_if)]
 fn find_executable_in_dir(dir: &Path, name: &str) -> Option<PathBuf> {
     if let Ok(entries) = fs::read_dir(dir) {
         for entry in entries.filter_map(|e| e.ok()) {
@@ -527,71 +2490,422 @@ fn find_executable_in_dir(dir: &Path, name: &str) -> Option<PathBuf> {
     None
 }
 
-fn install_all_cargo_binaries(install_location: &InstallLocation, build_dir: &Path) -> io::Result<()> {
-    let release_dir = build_dir.join("target/release");
+fn install_all_cargo_binaries(install_location: &InstallLocation, build_dir: &Path, repo_name: &str, opts: &InstallOptions) -> io::Result<Vec<PathBuf>> {
+    let release_dir = match &opts.target {
+        Some(target) => build_dir.join("target").join(target).join("release"),
+        None => build_dir.join("target/release"),
+    };
     let mut binaries = Vec::new();
     for entry in fs::read_dir(&release_dir)? {
         let entry = entry?;
         let path = entry.path();
-        if path.is_file() {
+        if path.is_file() && is_executable(&path) && !is_cargo_artifact(&path) && !is_excluded(&path, &opts.exclude) {
             binaries.push(path);
         }
     }
     if binaries.is_empty() {
-        return Err(io::Error::new(io::ErrorKind::NotFound, "No binaries found in target/release"));
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("No binaries found in {}", release_dir.display())));
+    }
+    binaries.sort();
+    if opts.select_binary && !opts.yes && binaries.len() > 1 {
+        binaries = select_binaries(binaries)?;
+    }
+    let name = opts.rename.as_deref().unwrap_or(repo_name);
+    let bin_names: Vec<String> = binaries.iter().map(|b| b.file_name().unwrap().to_string_lossy().to_string()).collect();
+    let original = if bin_names.len() == 1 { bin_names[0].as_str() } else { repo_name };
+    let dests: Vec<PathBuf> = bin_names.iter().map(|b| install_location.bin_path.join(renamed(b, original, name))).collect();
+    check_conflicts(&dests, name, opts)?;
+    let mut installed = Vec::new();
+    for (binary_path, bin_name) in binaries.iter().zip(&bin_names) {
+        installed.push(copy_binary(binary_path, install_location, renamed(bin_name, original, name))?);
+    }
+    Ok(installed)
+}
+
+fn shadowed_by(bin_dir: &Path, name: &str) -> Option<PathBuf> {
+    if !bin_dir.join(name).exists() {
+        return None;
+    }
+    let bin_dir = fs::canonicalize(bin_dir).ok()?;
+    for dir in env::split_paths(&env::var_os("PATH")?) {
+        if fs::canonicalize(&dir).is_ok_and(|d| d == bin_dir) {
+            return None;
+        }
+        let candidate = dir.join(name);
+        if candidate.is_file() && is_executable(&candidate) {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+fn warn_if_shadowed(bin_dir: &Path, name: &str) {
+    if let Some(earlier) = shadowed_by(bin_dir, name) {
+        println!("{}", color::paint(Yellow, format!(
+            "Warning: {} is shadowed by {}, which comes earlier in $PATH",
+            bin_dir.join(name).display(),
+            earlier.display()
+        )));
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+const CARGO_ARTIFACT_EXTENSIONS: [&str; 6] = ["d", "rlib", "rmeta", "so", "dylib", "a"];
+
+fn is_cargo_artifact(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| CARGO_ARTIFACT_EXTENSIONS.iter().any(|a| ext == *a))
+}
+
+fn select_binaries(binaries: Vec<PathBuf>) -> io::Result<Vec<PathBuf>> {
+    println!("{}", color::paint(Cyan.bold(), "Multiple binaries found. Select which to install:"));
+    for (i, binary) in binaries.iter().enumerate() {
+        println!("{}: {}", i + 1, binary.file_name().unwrap().to_string_lossy());
+    }
+    print!("~> Numbers separated by spaces or commas, empty for all: ");
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    if input.trim().is_empty() {
+        return Ok(binaries);
+    }
+    let mut selected = Vec::new();
+    for choice in input.split(|c: char| c == ',' || c.is_whitespace()).filter(|c| !c.is_empty()) {
+        let binary = choice
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|i| binaries.get(i))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid selection: {}", choice)))?;
+        if !selected.contains(binary) {
+            selected.push(binary.clone());
+        }
+    }
+    Ok(selected)
+}
+
+fn copy_binary(binary: &Path, install_location: &InstallLocation, name: &str) -> io::Result<PathBuf> {
+    let dest_path = install_location.bin_path.join(name);
+    if install_location.elevate {
+        run_command("cp", &[binary.to_str().unwrap(), dest_path.to_str().unwrap()], true, None, None)?;
+    } else {
+        fs::copy(binary, &dest_path)?;
+    }
+    Ok(dest_path)
+}
+
+fn renamed<'a>(file: &'a str, original: &str, name: &'a str) -> &'a str {
+    if file == original { name } else { file }
+}
+
+fn rename_binary(files: &mut Vec<PathBuf>, install_location: &InstallLocation, repo_name: &str, name: &str, opts: &InstallOptions) -> io::Result<()> {
+    let bin_path = install_location.bin_path.as_path();
+    if files.contains(&bin_path.join(name)) {
+        return Ok(());
+    }
+    let binaries: Vec<usize> = files.iter().enumerate().filter(|(_, f)| f.parent() == Some(bin_path)).map(|(i, _)| i).collect();
+    let index = match binaries.iter().find(|&&i| files[i].file_name().is_some_and(|f| f == repo_name)) {
+        Some(&i) => Some(i),
+        None if binaries.len() == 1 => Some(binaries[0]),
+        None if binaries.is_empty() && bin_path.join(repo_name).exists() => {
+            files.push(bin_path.join(repo_name));
+            Some(files.len() - 1)
+        }
+        None => None,
+    };
+    let Some(index) = index else {
+        return Err(io::Error::other(format!("Cannot tell which installed binary to rename to {}", name)));
+    };
+    let dest = bin_path.join(name);
+    if files[index] == dest {
+        return Ok(());
+    }
+    check_conflicts(std::slice::from_ref(&dest), name, opts)?;
+    println!("~> Renaming {} to {}", files[index].display(), dest.display());
+    run_command("mv", &["-f", files[index].to_str().unwrap(), dest.to_str().unwrap()], install_location.elevate, None, None)?;
+    files[index] = dest;
+    Ok(())
+}
+
+fn is_excluded(path: &Path, exclude: &[String]) -> bool {
+    path.file_name().is_some_and(|name| exclude.iter().any(|e| name == e.as_str()))
+}
+
+fn collect_staged(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.symlink_metadata()?.is_dir() {
+            collect_staged(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+const CABAL_BIN_DIR: &str = "charoite-cabal-bin";
+
+const STAGE_DIR: &str = "/tmp/charoite/stage";
+
+const DOC_DIRS: [&str; 3] = ["share/doc", "share/info", "share/gtk-doc"];
+
+fn install_staged(cmd: &str, args: &[&str], work_dir: &Path, build_dir: &Path, repo_name: &str, elevate: bool, opts: &InstallOptions) -> io::Result<Vec<PathBuf>> {
+    let exclude = &opts.exclude;
+    let name = opts.rename.as_deref().unwrap_or(repo_name);
+    let stage = Path::new(STAGE_DIR).join(build_dir.file_name().unwrap());
+    if stage.exists() {
+        fs::remove_dir_all(&stage)?;
+    }
+    fs::create_dir_all(&stage)?;
+    let destdir_var = format!("DESTDIR={}", stage.display());
+    let mut env_args = vec![destdir_var.as_str(), cmd];
+    env_args.extend(args);
+    if let Err(e) = run_build_command("env", &env_args, work_dir, build_dir, None, opts) {
+        let _ = fs::remove_dir_all(&stage);
+        return Err(e);
+    }
+
+    let mut staged = Vec::new();
+    collect_staged(&stage, &mut staged)?;
+    let mut copies = Vec::new();
+    for file in staged {
+        if is_excluded(&file, exclude) {
+            println!("~> Skipping excluded file {}", file.display());
+            continue;
+        }
+        let mut dest = Path::new("/").join(file.strip_prefix(&stage).unwrap());
+        if dest.file_name().is_some_and(|f| f == repo_name) {
+            dest.set_file_name(name);
+        }
+        if let Some(doc) = doc_path(&dest) {
+            if opts.no_docs {
+                continue;
+            }
+            if let Some(docdir) = &opts.docdir {
+                dest = docdir.join(doc);
+            }
+        }
+        copies.push((file, dest));
     }
-    for binary_path in binaries {
-        let bin_name = binary_path.file_name().unwrap();
-        let dest_path = install_location.bin_path.join(bin_name);
-        if install_location.elevate {
-            run_command("cp", &[binary_path.to_str().unwrap(), dest_path.to_str().unwrap()], true, None)?;
+    let dests: Vec<PathBuf> = copies.iter().map(|(_, dest)| dest.clone()).collect();
+    if let Err(e) = check_conflicts(&dests, name, opts) {
+        let _ = fs::remove_dir_all(&stage);
+        return Err(e);
+    }
+
+    let mut pending = Vec::new();
+    for (file, dest) in copies {
+        let temp = dest.with_file_name(format!(".{}.charoite-new", dest.file_name().unwrap().to_string_lossy()));
+        if let Err(e) = copy_staged_file(&file, &temp, elevate) {
+            eprintln!("{}: failed to copy {}, rolling back", color::paint(Red, "Error"), dest.display());
+            let temps: Vec<PathBuf> = pending.into_iter().map(|(temp, _)| temp).chain([temp]).collect();
+            remove_installed(&temps, elevate);
+            let _ = fs::remove_dir_all(&stage);
+            return Err(e);
+        }
+        pending.push((temp, dest));
+    }
+
+    let mut installed = Vec::new();
+    let mut backups = Vec::new();
+    for (temp, dest) in &pending {
+        let backup = dest.with_file_name(format!(".{}.charoite-old", dest.file_name().unwrap().to_string_lossy()));
+        let moved = if dest.symlink_metadata().is_ok_and(|m| !m.is_dir()) {
+            run_command("mv", &["-f", dest.to_str().unwrap(), backup.to_str().unwrap()], elevate, None, None)
+                .map(|_| backups.push((backup, dest.clone())))
         } else {
-            fs::copy(&binary_path, &dest_path)?;
+            Ok(())
+        }
+        .and_then(|_| run_command("mv", &["-f", temp.to_str().unwrap(), dest.to_str().unwrap()], elevate, None, None));
+        if let Err(e) = moved {
+            eprintln!("{}: failed to move {} into place, rolling back", color::paint(Red, "Error"), dest.display());
+            remove_installed(&installed, elevate);
+            for (backup, dest) in &backups {
+                let _ = run_command("mv", &["-f", backup.to_str().unwrap(), dest.to_str().unwrap()], elevate, None, None);
+            }
+            remove_installed(&pending.iter().map(|(temp, _)| temp.clone()).collect::<Vec<_>>(), elevate);
+            let _ = fs::remove_dir_all(&stage);
+            return Err(e);
+        }
+        installed.push(dest.clone());
+    }
+    remove_installed(&backups.into_iter().map(|(backup, _)| backup).collect::<Vec<_>>(), elevate);
+    fs::remove_dir_all(&stage)?;
+    Ok(installed)
+}
+
+fn doc_path(dest: &Path) -> Option<PathBuf> {
+    let dir = dest.ancestors().skip(1).find(|dir| DOC_DIRS.iter().any(|d| dir.ends_with(d)))?;
+    let base = if dir.ends_with("share/doc") { dir } else { dir.parent()? };
+    dest.strip_prefix(base).ok().map(Path::to_path_buf)
+}
+
+fn copy_staged_file(file: &Path, dest: &Path, elevate: bool) -> io::Result<()> {
+    let dest_dir = dest.parent().unwrap();
+    if !dest_dir.exists() {
+        run_command("mkdir", &["-p", dest_dir.to_str().unwrap()], elevate, None, None)?;
+    }
+    let mut cp_args = vec!["-P"];
+    if !utils::is_bsd() {
+        cp_args.push("--preserve=mode,timestamps");
+    }
+    cp_args.extend([file.to_str().unwrap(), dest.to_str().unwrap()]);
+    run_command("cp", &cp_args, elevate, None, None)
+}
+
+fn validate_permissions(opts: &InstallOptions) -> io::Result<()> {
+    if let Some(mode) = &opts.mode
+        && (!(3..=4).contains(&mode.len()) || !mode.chars().all(|c| ('0'..='7').contains(&c)))
+    {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid --mode '{}': expected octal like 755 or 2755", mode)));
+    }
+    if let Some(owner) = &opts.owner
+        && (owner.is_empty() || owner.starts_with(':') || owner.split(':').count() > 2)
+    {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid --owner '{}': expected user or user:group", owner)));
+    }
+    Ok(())
+}
+
+fn apply_permissions(binaries: &[&PathBuf], opts: &InstallOptions, elevate: bool) -> io::Result<()> {
+    for binary in binaries {
+        let path = binary.to_str().unwrap();
+        if let Some(owner) = &opts.owner {
+            println!("~> chown {} {}", owner, path);
+            run_command("chown", &[owner, path], true, None, None)?;
+        }
+        if let Some(mode) = &opts.mode {
+            println!("~> chmod {} {}", mode, path);
+            run_command("chmod", &[mode, path], elevate || opts.owner.is_some(), None, None)?;
         }
     }
     Ok(())
 }
 
+fn remove_installed(files: &[PathBuf], elevate: bool) {
+    for file in files {
+        let _ = run_command("rm", &["-f", file.to_str().unwrap()], elevate, None, None);
+    }
+}
+
 fn install_project(
     build_system: BuildSystem,
     install_location: &InstallLocation,
     build_dir: &Path,
     repo_name: &str,
-) -> io::Result<()> {
+    opts: &InstallOptions,
+) -> io::Result<Vec<PathBuf>> {
+    let prefix = install_location.bin_path.parent().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid bin path"))?.to_str().unwrap();
+    let elevate = install_location.elevate;
+    let relocated = opts.stow || opts.local || opts.prefix.is_some();
+    let name = opts.rename.as_deref().unwrap_or(repo_name);
+    let install_binary = |binary: &Path| {
+        check_conflicts(&[install_location.bin_path.join(name)], name, opts)?;
+        copy_binary(binary, install_location, name)
+    };
+    if let Some(target) = &opts.build_target && supports_build_target(build_system) {
+        return install_build_target(build_system, install_location, build_dir, target, repo_name, opts);
+    }
     match build_system {
-        BuildSystem::Cargo => install_all_cargo_binaries(install_location, build_dir),
+        BuildSystem::Cargo => install_all_cargo_binaries(install_location, build_dir, repo_name, opts),
         BuildSystem::Make => {
-            let prefix = install_location.bin_path.parent().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid bin path"))?.to_str().unwrap();
             let prefix_arg = format!("PREFIX={}", prefix);
-            run_command("make", &["install", &prefix_arg], install_location.elevate, Some(build_dir))
+            let target = make_install_target(build_system, build_dir, opts);
+            let args = ["-f", makefile(build_dir, opts), &target, &prefix_arg];
+            install_staged(utils::make_program(build_dir), &args, build_dir, build_dir, repo_name, elevate, opts)
+        }
+        BuildSystem::Autotools => {
+            let prefix_arg = format!("prefix={}", prefix);
+            let make_dir = make_dir(build_system, build_dir, opts);
+            let target = make_install_target(build_system, build_dir, opts);
+            let mut args = vec![target.as_str()];
+            if relocated {
+                args.push(&prefix_arg);
+            }
+            install_staged(utils::make_program(build_dir), &args, &make_dir, build_dir, repo_name, elevate, opts)
+        }
+        BuildSystem::Cmake => {
+            let mut args = vec!["--install", "."];
+            if relocated {
+                args.extend(["--prefix", prefix]);
+            }
+            install_staged("cmake", &args, &cmake_build_path(build_dir, opts)?, build_dir, repo_name, elevate, opts)
+        }
+        BuildSystem::Meson | BuildSystem::Ninja => install_staged("ninja", &["install"], &build_dir.join("build"), build_dir, repo_name, elevate, opts),
+        BuildSystem::Nimble => {
+            let root_binary = build_dir.join(repo_name);
+            let binary = find_executable_in_dir(&build_dir.join("bin"), repo_name)
+                .or_else(|| root_binary.is_file().then_some(root_binary));
+            match binary {
+                Some(binary) => Ok(vec![install_binary(&binary)?]),
+                None => Err(io::Error::new(io::ErrorKind::NotFound, "Binary not found")),
+            }
         }
-        BuildSystem::Autotools => run_command("make", &["install"], install_location.elevate, Some(build_dir)),
-        BuildSystem::Cmake => run_command("cmake", &["--install", "."], install_location.elevate, Some(&build_dir.join("build"))),
-        BuildSystem::Meson | BuildSystem::Ninja => run_command("ninja", &["install"], install_location.elevate, Some(&build_dir.join("build"))),
-        BuildSystem::Nimble => run_command("nimble", &["install"], install_location.elevate, Some(build_dir)),
         BuildSystem::Stack => {
             let bin_dir = build_dir.join("bin");
             if let Some(binary) = find_executable_in_dir(&bin_dir, repo_name) {
-                let dest_path = install_location.bin_path.join(repo_name);
-                if install_location.elevate {
-                    run_command("cp", &[binary.to_str().unwrap(), dest_path.to_str().unwrap()], true, None)
-                } else {
-                    fs::copy(&binary, &dest_path).map(|_| ())
-                }
+                Ok(vec![install_binary(&binary)?])
             } else {
                 Err(io::Error::new(io::ErrorKind::NotFound, "Binary not found"))
             }
         }
+        BuildSystem::Cabal => {
+            let bin_dir = build_dir.join(CABAL_BIN_DIR);
+            let mut binaries: Vec<PathBuf> = fs::read_dir(&bin_dir)?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && is_executable(p) && !is_excluded(p, &opts.exclude))
+                .collect();
+            binaries.sort();
+            if binaries.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "Binary not found"));
+            }
+            let bin_names: Vec<String> = binaries.iter().map(|b| b.file_name().unwrap().to_string_lossy().to_string()).collect();
+            let original = if bin_names.len() == 1 { bin_names[0].as_str() } else { repo_name };
+            let dests: Vec<PathBuf> = bin_names.iter().map(|b| install_location.bin_path.join(renamed(b, original, name))).collect();
+            check_conflicts(&dests, name, opts)?;
+            let mut installed = Vec::new();
+            for (binary, bin_name) in binaries.iter().zip(&bin_names) {
+                match copy_binary(binary, install_location, renamed(bin_name, original, name)) {
+                    Ok(path) => installed.push(path),
+                    Err(e) => {
+                        remove_installed(&installed, elevate);
+                        return Err(e);
+                    }
+                }
+            }
+            Ok(installed)
+        }
+        BuildSystem::Custom => match &opts.install_cmd {
+            Some(install_cmd) => {
+                if name != repo_name {
+                    check_conflicts(&[install_location.bin_path.join(repo_name)], name, opts)?;
+                }
+                let prefix_var = format!("PREFIX={}", prefix);
+                let bindir_var = format!("BINDIR={}", install_location.bin_path.display());
+                run_command("env", &[&prefix_var, &bindir_var, "sh", "-c", install_cmd], elevate, Some(build_dir), None)?;
+                Ok(Vec::new())
+            }
+            None => match find_executable_in_dir(build_dir, repo_name) {
+                Some(binary) => Ok(vec![install_binary(&binary)?]),
+                None => Err(io::Error::new(io::ErrorKind::NotFound, "Binary not found, pass --install-cmd")),
+            },
+        },
+        #[allow(clippy::io_other_error)]
         BuildSystem::Pip => {
             if !check_dependency("pip") {
                 return Err(io::Error::new(io::ErrorKind::NotFound, "pip not found"));
             }
-            let pip_command = if install_location.elevate {
+            let mut pip_command = if elevate {
                 vec!["pip", "install", "."]
             } else {
                 vec!["pip", "install", "--user", "."]
             };
-            let status = if install_location.elevate {
+            if opts.offline {
+                pip_command.insert(2, "--no-index");
+            }
+            let status = if elevate {
                 Command::new(utils::get_privilege_command())
                     .args(&pip_command)
                     .status()
@@ -602,7 +2916,7 @@ fn install_project(
             };
             if let Ok(status) = status {
                 if status.success() {
-                    Ok(())
+                    Ok(Vec::new())
                 } else {
                     Err(io::Error::new(io::ErrorKind::Other, "pip install failed"))
                 }
@@ -614,52 +2928,156 @@ fn install_project(
     }
 }
 
-fn update_installed_packages(
-    repo_name: &str,
-    source: Option<&str>,
-    build_system: BuildSystem,
-    location: &Path,
-    build_file: Option<&String>,
-    hash: Option<String>,
-    version: Option<String>,
-    commit_hash: Option<String>,
-    install_date: Option<String>,
-    last_commit_date: Option<String>,
-) {
-    let etc_path = Path::new("/etc/charoite");
-    if !etc_path.exists() {
-        fs::create_dir_all(etc_path).expect("Failed to create /etc/charoite");
-    }
-    let installed_path = etc_path.join("installed.yaml");
-    let mut installed: Vec<InstalledPackage> = if installed_path.exists() {
-        let content = fs::read_to_string(&installed_path).unwrap_or_default();
-        serde_yaml::from_str(&content).unwrap_or_default()
+fn collect_files(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.is_dir() && depth > 0 {
+                collect_files(&path, depth - 1, files);
+            } else if path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+}
+
+fn find_extras(build_dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let mut extras = Vec::new();
+
+    let mut completions = Vec::new();
+    for dir in ["completions", "completion", "contrib/completions", "shell-completions"] {
+        collect_files(&build_dir.join(dir), 2, &mut completions);
+    }
+    for file in completions {
+        let name = file.file_name().unwrap().to_string_lossy().to_string();
+        let parent = file.parent().and_then(|p| p.file_name()).map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+        let ext = file.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+        let dest = if ext == "bash" || parent == "bash" {
+            let stem = file.file_stem().unwrap().to_string_lossy().to_string();
+            Path::new("bash-completion/completions").join(if ext == "bash" { stem } else { name })
+        } else if ext == "fish" || parent == "fish" {
+            Path::new("fish/vendor_completions.d").join(name)
+        } else if name.starts_with('_') || parent == "zsh" {
+            Path::new("zsh/site-functions").join(name)
+        } else {
+            continue;
+        };
+        extras.push((file, dest));
+    }
+
+    let mut pages = Vec::new();
+    for dir in ["man", "doc", "docs"] {
+        collect_files(&build_dir.join(dir), 2, &mut pages);
+    }
+    for file in pages {
+        let name = file.file_name().unwrap().to_string_lossy().to_string();
+        let section = name
+            .strip_suffix(".gz")
+            .unwrap_or(&name)
+            .rsplit_once('.')
+            .map(|(_, s)| s.to_string())
+            .filter(|s| s.len() == 1 && matches!(s.as_bytes()[0], b'1'..=b'8'));
+        if let Some(section) = section {
+            extras.push((file, Path::new("man").join(format!("man{}", section)).join(name)));
+        }
+    }
+    extras
+}
+
+fn install_extras(build_dir: &Path, install_location: &InstallLocation) -> io::Result<Vec<PathBuf>> {
+    let share_dir = if install_location.elevate && !utils::is_bsd() {
+        PathBuf::from("/usr/share")
     } else {
-        Vec::new()
-    };
-    
-    let pkg = InstalledPackage {
-        name: repo_name.to_string(),
-        source: source.map(|s| s.to_string()),
-        build_system: format!("{:?}", build_system),
-        location: location.to_string_lossy().to_string(),
-        build_file: build_file.cloned(),
-        hash,
-        version,
-        last_commit_hash: commit_hash,
-        install_date,
-        last_commit_date,
-    };
-    
-    installed.retain(|p| p.name != repo_name);
-    installed.push(pkg);
-    
-    let temp_path = Path::new("/tmp").join("charoite-installed.yaml");
-    fs::write(&temp_path, serde_yaml::to_string(&installed).unwrap()).unwrap();
-    Command::new(&utils::get_privilege_command())
-        .arg("mv")
-        .arg(&temp_path)
-        .arg(&installed_path)
-        .status()
-        .expect("Failed to update package list");
+        install_location.bin_path.parent().unwrap_or(&install_location.bin_path).join("share")
+    };
+    let mut installed = Vec::new();
+    for (source, dest) in find_extras(build_dir) {
+        let dest = share_dir.join(dest);
+        println!("~> Installing {}", dest.display());
+        let dest_dir = dest.parent().unwrap();
+        let copied = run_command("mkdir", &["-p", dest_dir.to_str().unwrap()], install_location.elevate, None, None)
+            .and_then(|_| run_command("cp", &[source.to_str().unwrap(), dest.to_str().unwrap()], install_location.elevate, None, None));
+        if let Err(e) = copied {
+            remove_installed(&installed, install_location.elevate);
+            return Err(e);
+        }
+        installed.push(dest);
+    }
+    if installed.is_empty() {
+        println!("{}", color::paint(Yellow, "Warning: no completions or man pages found"));
+    }
+    Ok(installed)
+}
+
+fn stow_path(install_location: &InstallLocation, repo_name: &str) -> PathBuf {
+    let prefix = install_location.bin_path.parent().unwrap_or(&install_location.bin_path);
+    prefix.join("stow").join(repo_name)
+}
+
+fn install_stowed(
+    build_system: BuildSystem,
+    install_location: &InstallLocation,
+    stow_dir: &Path,
+    build_dir: &Path,
+    repo_name: &str,
+    opts: &InstallOptions,
+) -> io::Result<Vec<PathBuf>> {
+    let elevate = install_location.elevate;
+    let stow_dir_str = stow_dir.to_str().unwrap();
+    if stow_dir.exists() {
+        run_command("rm", &["-rf", stow_dir_str], elevate, None, None)?;
+    }
+    let stow_bin = stow_dir.join("bin");
+    run_command("mkdir", &["-p", stow_bin.to_str().unwrap()], elevate, None, None)?;
+    let stow_location = InstallLocation { bin_path: stow_bin.clone(), elevate };
+    let mut files = install_project(build_system, &stow_location, build_dir, repo_name, opts)?;
+
+    let mut links = Vec::new();
+    for entry in fs::read_dir(&stow_bin)? {
+        let target = entry?.path();
+        if is_excluded(&target, &opts.exclude) {
+            println!("~> Skipping excluded binary {}", target.display());
+            continue;
+        }
+        let link = install_location.bin_path.join(target.file_name().unwrap());
+        let owned_by_stow = fs::read_link(&link).map(|t| t.starts_with(stow_dir)).unwrap_or(false);
+        if link.symlink_metadata().is_ok() && !owned_by_stow {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Conflict: {} already exists and is not managed by {}", link.display(), stow_dir.display()),
+            ));
+        }
+        links.push((target, link));
+    }
+    for (target, link) in links {
+        println!("~> Linking {} -> {}", link.display(), target.display());
+        run_command("ln", &["-sf", target.to_str().unwrap(), link.to_str().unwrap()], elevate, None, None)?;
+        files.push(link);
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cargo_deps_reads_charoite_metadata() {
+        let dir = env::temp_dir().join("charoite-test-cargo-deps");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Cargo.toml"), r#"
+[package]
+name = "demo"
+version = "0.1.0"
+
+[package.metadata.charoite]
+dependencies = ["openssl", "libgit2"]
+
+[dependencies]
+serde = "1.0"
+"#).unwrap();
+        let deps = parse_cargo_deps(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(deps, vec!["openssl".to_string(), "libgit2".to_string()]);
+    }
 }
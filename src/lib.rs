@@ -0,0 +1,24 @@
+mod archive;
+pub mod batch;
+pub mod color;
+pub mod config;
+pub mod distro;
+pub mod doctor;
+pub mod history;
+pub mod install;
+pub mod list;
+pub mod porcelain;
+pub mod registry;
+pub mod remove;
+pub mod sandbox;
+pub mod search;
+pub mod source;
+pub mod state;
+pub mod update;
+pub mod utils;
+pub mod verify;
+
+pub use install::{InstallOptions, Installer};
+pub use source::Source;
+pub use registry::Registry;
+pub use utils::InstalledPackage;
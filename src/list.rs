@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use ansi_term::Colour::{Green, Red, Yellow};
+use ansi_term::Style;
+use chrono::NaiveDate;
+use crate::color;
+use crate::registry::Registry;
+use crate::update::{self, RemoteState};
+use crate::utils::InstalledPackage;
+
+fn cache_path() -> PathBuf {
+    let cache_home = env::var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(env::var("HOME").unwrap_or_default()).join(".cache"));
+    cache_home.join("charoite/remote-heads.json")
+}
+
+pub fn load_remote_heads() -> HashMap<String, String> {
+    fs::read_to_string(cache_path())
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_remote_heads(heads: &HashMap<String, String>) -> io::Result<()> {
+    let path = cache_path();
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, serde_json::to_string(heads)?)
+}
+
+pub fn list(check: bool, parallel: usize, group: Option<&str>) -> io::Result<()> {
+    let registry = Registry::load()?;
+    let packages: Vec<&InstalledPackage> = registry
+        .packages()
+        .iter()
+        .filter(|p| group.is_none_or(|g| p.group.as_deref() == Some(g)))
+        .collect();
+    if packages.is_empty() {
+        match group {
+            Some(group) => println!("No packages in group {}", group),
+            None => println!("No packages installed"),
+        }
+        return Ok(());
+    }
+
+    let mut heads = load_remote_heads();
+    if check {
+        let tracked: Vec<&InstalledPackage> = packages.iter().copied().filter(|p| p.repo.is_some()).collect();
+        println!("{}", color::bold(format!("~> Checking {} package(s) for updates", tracked.len())));
+        for (pkg, state) in tracked.iter().zip(update::check_remotes(&tracked, parallel.max(1))) {
+            match state {
+                RemoteState::UpToDate => {
+                    heads.insert(pkg.name.clone(), pkg.last_commit_hash.clone().unwrap_or_default());
+                }
+                RemoteState::Outdated(head) => {
+                    heads.insert(pkg.name.clone(), head);
+                }
+                RemoteState::Failed(e) => eprintln!("{}: {}", pkg.name, color::paint(Red, e)),
+            }
+        }
+        if let Err(e) = save_remote_heads(&heads) {
+            eprintln!("Failed to write update cache: {}", e);
+        }
+    }
+
+    println!("{:<24} {:<10} {:<10} {:<12} {:<18} Location", "Package", "Commit", "Installed", "Group", "Status");
+    println!("{}", "-".repeat(93));
+    for pkg in packages {
+        let (label, style) = status(pkg, heads.get(&pkg.name));
+        let status = color::paint(style, format!("{:<18}", label));
+        println!(
+            "{:<24} {:<10} {:<10} {:<12} {} {}",
+            pkg.name,
+            update::short_hash(pkg.last_commit_hash.as_deref()) + if pkg.dirty { "+" } else { "" },
+            pkg.install_date.as_deref().unwrap_or("-"),
+            pkg.group.as_deref().unwrap_or("-"),
+            status,
+            pkg.location
+        );
+    }
+    if !check {
+        println!("\nRun `charoite list --check` to refresh update information");
+    }
+    Ok(())
+}
+
+pub fn metrics(group: Option<&str>) -> io::Result<()> {
+    let registry = Registry::load()?;
+    let packages: Vec<&InstalledPackage> = registry
+        .packages()
+        .iter()
+        .filter(|p| group.is_none_or(|g| p.group.as_deref() == Some(g)))
+        .collect();
+    let heads = load_remote_heads();
+
+    println!("# HELP charoite_packages_installed Number of packages installed by charoite.");
+    println!("# TYPE charoite_packages_installed gauge");
+    println!("charoite_packages_installed {}", packages.len());
+
+    println!("# HELP charoite_package_install_timestamp_seconds Date the package was last installed.");
+    println!("# TYPE charoite_package_install_timestamp_seconds gauge");
+    for pkg in &packages {
+        let date = pkg.install_date.as_deref().and_then(|d| NaiveDate::parse_from_str(d, "%y-%m-%d").ok());
+        if let Some(timestamp) = date.and_then(|d| d.and_hms_opt(0, 0, 0)).map(|d| d.and_utc().timestamp()) {
+            println!("charoite_package_install_timestamp_seconds{{{}}} {}", labels(pkg), timestamp);
+        }
+    }
+
+    println!("# HELP charoite_package_build_duration_seconds Time the last install of the package took.");
+    println!("# TYPE charoite_package_build_duration_seconds gauge");
+    for pkg in &packages {
+        if let Some(duration) = pkg.build_duration {
+            println!("charoite_package_build_duration_seconds{{{}}} {}", labels(pkg), duration);
+        }
+    }
+
+    println!("# HELP charoite_package_update_available Whether the cached upstream commit differs from the installed one.");
+    println!("# TYPE charoite_package_update_available gauge");
+    for pkg in &packages {
+        if let (Some(head), Some(installed)) = (heads.get(&pkg.name), &pkg.last_commit_hash) {
+            println!("charoite_package_update_available{{{}}} {}", labels(pkg), u8::from(head != installed));
+        }
+    }
+    Ok(())
+}
+
+fn labels(pkg: &InstalledPackage) -> String {
+    let escape = |v: &str| v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    format!(
+        "package=\"{}\",group=\"{}\",build_system=\"{}\"",
+        escape(&pkg.name),
+        escape(pkg.group.as_deref().unwrap_or("")),
+        escape(&pkg.build_system),
+    )
+}
+
+fn status(pkg: &InstalledPackage, head: Option<&String>) -> (&'static str, Style) {
+    if !Path::new(&pkg.location).exists() {
+        return ("missing binary", Red.normal());
+    }
+    match (head, &pkg.last_commit_hash) {
+        (Some(head), Some(installed)) if head == installed => ("up to date", Green.normal()),
+        (Some(_), Some(_)) => ("update available", Yellow.normal()),
+        _ => ("unknown", Style::new()),
+    }
+}
@@ -1,28 +1,193 @@
 mod cli;
-mod install;
-mod search;
-mod utils;
-mod remove;
 
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use clap::Parser;
+use ansi_term::Colour::Purple;
+use charoite::{color, config, doctor, history, install, list, porcelain, remove, search, state, update, verify, InstallOptions, Installer, Registry, Source};
+use charoite::install::Outcome;
+use charoite::porcelain::Status;
+use charoite::search::Format;
 use crate::cli::{Cli, Command};
 
 fn main() -> io::Result<()> {
     let cli = Cli::parse();
+    color::init(cli.no_color);
+    if let Some(path) = cli.config {
+        config::set_config_path(path);
+    }
+    if let Some(path) = cli.registry.or_else(|| cli.registry_dir.map(|d| d.join("installed.yaml"))) {
+        Registry::set_path(path);
+    }
     match cli.command {
-        Command::Install { repo, local, gitlab, codeberg, branch, patches, flags, yes } => {
-            let patches_path = patches.as_deref().map(Path::new);
-            install::install(&repo, local, gitlab, codeberg, branch.as_deref(), patches_path, &flags, yes)
+        Command::Install { repo, path, script, bundle, allow_dirty, local, prefix, output_dir, print_install_path, rename, source, gitlab, codeberg, branch, commit, mirror, depth, blobless, patches, flags, build_target, install_target, strip, docdir, no_docs, bindir, libdir, includedir, no_default_flags, user_flags_first, porcelain, yes, resume, show_log, stow, force, cmake_generator, cmake_preset, tarball, exclude, quiet_git, build_cmd, install_cmd, check_only, only_deps, extras, no_verify_pkg_config, jobs, rustflags, codegen_units, target, cmake_toolchain, out_of_tree, verify_deps_versions, sandbox, sandbox_image, allow_downgrade, edit_flags, review, prefer, build_file, strip_components, patch_from, patch_urls, select_binary, group, recurse_submodules, mode, owner, offline } => {
+            let prefix = prefix.map(std::path::absolute).transpose()?;
+            if print_install_path {
+                let location = install::get_install_path(local, prefix.as_deref());
+                println!("{}", location.bin_path.display());
+                println!("elevation: {}", if location.elevate { "required" } else { "not required" });
+                return Ok(());
+            }
+            if porcelain {
+                porcelain::init()?;
+            }
+            let source = match (&path, &bundle) {
+                (Some(_), _) => Some(Source::Path),
+                (_, Some(_)) => Some(Source::Bundle),
+                _ => source.or_else(|| deprecated_source(gitlab, codeberg)),
+            };
+            let repo = repo.or(path).or(bundle).unwrap_or_default();
+            let opts = InstallOptions {
+                local,
+                prefix,
+                rename,
+                source,
+                branch,
+                commit,
+                mirror,
+                depth,
+                blobless,
+                patches: patches.map(PathBuf::from),
+                flags,
+                no_default_flags,
+                user_flags_first,
+                yes,
+                resume,
+                show_log,
+                stow,
+                force,
+                cmake_generator,
+                cmake_preset,
+                tarball,
+                exclude,
+                quiet_git,
+                build_cmd,
+                install_cmd,
+                check_only,
+                only_deps,
+                extras,
+                no_verify_pkg_config,
+                jobs,
+                rustflags,
+                codegen_units,
+                target,
+                cmake_toolchain,
+                out_of_tree,
+                verify_deps_versions,
+                sandbox,
+                sandbox_image,
+                allow_downgrade,
+                edit_flags,
+                review,
+                prefer,
+                build_file,
+                strip_components,
+                patch_from,
+                patch_urls,
+                select_binary,
+                group,
+                recurse_submodules,
+                mode,
+                owner,
+                offline,
+                allow_dirty,
+                script,
+                output_dir,
+                build_target,
+                install_target,
+                strip,
+                docdir,
+                no_docs,
+                bindir,
+                libdir,
+                includedir,
+                ..Default::default()
+            };
+            let start = Instant::now();
+            let rename = opts.rename.clone();
+            let local = opts.local && opts.prefix.is_none();
+            let result = Installer::new(&repo).options(opts).install();
+            if !matches!(result, Ok(Outcome::Skipped)) {
+                history::record("install", &repo, local, &result, start.elapsed());
+            }
+            if porcelain {
+                let (name, commit) = installed_package(&repo, rename.as_deref());
+                match result {
+                    Ok(Outcome::Skipped) => porcelain::emit(Status::Skipped, &name, "nothing installed"),
+                    _ => porcelain::report(Status::Installed, &name, &result, &commit),
+                }
+            }
+            result.map(|_| ())
         }
-        Command::Search { query } => {
-            println!("\x1b[1;35mSearching for {}...\x1b[0m", query);
-            search::search(&query);
+        Command::Search { query, interactive, format, min_stars, no_forks, refresh, yes } => {
+            if format == Format::Table {
+                println!("{}", color::paint(Purple.bold(), format!("Searching for {}...", query)));
+            }
+            let results = search::cached_search(&query, search::Filters { min_stars, no_forks }, refresh)?;
+            search::output_results(&results, format)?;
+            if interactive {
+                if search::interactive_available(yes) {
+                    search::pick_and_install(&results)?;
+                } else {
+                    eprintln!("Interactive selection disabled (--yes or not a terminal)");
+                }
+            }
             Ok(())
         }
-        Command::Remove { name } => {
-            remove::remove_package(&name)
+        Command::List { metrics: true, group, .. } => list::metrics(group.as_deref()),
+        Command::List { check, parallel, group, .. } => list::list(check, parallel, group.as_deref()),
+        Command::Remove { name, group, keep_libs, keep_going, local, porcelain } => {
+            if porcelain {
+                porcelain::init()?;
+            }
+            let Some(name) = name else {
+                return remove::remove_group(&group.unwrap_or_default(), keep_libs, keep_going);
+            };
+            let start = Instant::now();
+            let local = Registry::load().ok().and_then(|r| r.get(&name, local).map(|p| p.local)).unwrap_or(local);
+            let result = remove::remove_package(&name, keep_libs, local);
+            history::record("remove", &name, local, &result, start.elapsed());
+            porcelain::report(Status::Removed, &name, &result, "");
+            result
         }
+        Command::Doctor => doctor::doctor(),
+        Command::Update { name, parallel, yes, prefetch, show_commits, keep_going, local, porcelain, .. } => {
+            if porcelain {
+                porcelain::init()?;
+            }
+            update::update(name.as_deref(), local, parallel, yes, prefetch, show_commits, keep_going)
+        }
+        Command::Pin { name, local } => update::set_pinned(&name, local, true),
+        Command::Unpin { name, local } => update::set_pinned(&name, local, false),
+        Command::SelfUpdate { yes } => update::self_update(yes),
+        Command::Verify { name, local } => verify::verify(&name, local),
+        Command::Outdated { parallel } => update::outdated(parallel),
+        Command::History { limit, since, package } => history::show(limit, since.as_deref(), package.as_deref()),
+        Command::Export { file } => state::export(Path::new(&file)),
+        Command::Import { file, rebuild, yes, prefetch, keep_going } => state::import(Path::new(&file), rebuild, yes, prefetch, keep_going),
+    }
+}
+
+fn installed_package(repo: &str, rename: Option<&str>) -> (String, String) {
+    let registry = Registry::load().ok();
+    let pkg = registry.as_ref().and_then(|r| {
+        r.packages()
+            .iter()
+            .find(|p| p.repo.as_deref() == Some(repo) && rename.is_none_or(|n| p.name == n))
+    });
+    match pkg {
+        Some(pkg) => (pkg.name.clone(), pkg.last_commit_hash.as_deref().unwrap_or_default().chars().take(7).collect()),
+        None => (rename.unwrap_or(repo).to_string(), String::new()),
     }
 }
+
+fn deprecated_source(gitlab: bool, codeberg: bool) -> Option<Source> {
+    let (flag, source) = match (gitlab, codeberg) {
+        (true, _) => ("--gitlab", Source::Gitlab),
+        (_, true) => ("--codeberg", Source::Codeberg),
+        _ => return None,
+    };
+    eprintln!("{} is deprecated; use --source {}", flag, source);
+    Some(source)
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::AsFd;
+use std::sync::OnceLock;
+
+static OUTPUT: OnceLock<File> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Installed,
+    Updated,
+    Removed,
+    UpToDate,
+    Skipped,
+    Failed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Status::Installed => "INSTALLED",
+            Status::Updated => "UPDATED",
+            Status::Removed => "REMOVED",
+            Status::UpToDate => "UPTODATE",
+            Status::Skipped => "SKIPPED",
+            Status::Failed => "FAILED",
+        })
+    }
+}
+
+pub fn init() -> io::Result<()> {
+    if OUTPUT.get().is_some() {
+        return Ok(());
+    }
+    let stdout = io::stdout().as_fd().try_clone_to_owned()?;
+    rustix::stdio::dup2_stdout(io::stderr())?;
+    let _ = OUTPUT.set(File::from(stdout));
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    OUTPUT.get().is_some()
+}
+
+pub fn emit(status: Status, name: &str, detail: &str) {
+    let Some(mut out) = OUTPUT.get() else { return };
+    let _ = writeln!(out, "{}\t{}\t{}", status, clean(name), clean(detail));
+}
+
+pub fn report<T>(status: Status, name: &str, result: &io::Result<T>, detail: &str) {
+    match result {
+        Ok(_) => emit(status, name, detail),
+        Err(e) => emit(Status::Failed, name, &e.to_string()),
+    }
+}
+
+fn clean(text: &str) -> String {
+    text.lines().next().unwrap_or_default().replace('\t', " ")
+}
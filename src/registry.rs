@@ -0,0 +1,159 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use crate::config::Config;
+use crate::utils::{self, InstalledPackage};
+
+static PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Debug, Default)]
+pub struct Registry {
+    packages: Vec<InstalledPackage>,
+    saved: (String, String),
+}
+
+impl Registry {
+    pub fn path() -> PathBuf {
+        PATH_OVERRIDE.get().cloned().unwrap_or_else(|| Self::dir().join("installed.yaml"))
+    }
+
+    pub fn dir() -> PathBuf {
+        if let Some(home) = env::var_os("CHAROITE_HOME").filter(|h| !h.is_empty()) {
+            return PathBuf::from(home);
+        }
+        Config::load().registry_dir.unwrap_or_else(|| PathBuf::from("/etc/charoite"))
+    }
+
+    pub fn set_path(path: PathBuf) {
+        let _ = PATH_OVERRIDE.set(path);
+    }
+
+    pub fn user_path() -> PathBuf {
+        let relocated = PATH_OVERRIDE.get().is_some()
+            || env::var_os("CHAROITE_HOME").is_some_and(|h| !h.is_empty())
+            || Config::load().registry_dir.is_some();
+        if relocated {
+            return Self::path();
+        }
+        let data_home = env::var("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from(env::var("HOME").unwrap_or_default()).join(".local/share"));
+        data_home.join("charoite/installed.yaml")
+    }
+
+    pub fn load() -> io::Result<Registry> {
+        let (system_path, user_path) = (Self::path(), Self::user_path());
+        let mut packages = read_packages(&system_path)?;
+        if user_path != system_path {
+            let mut local = read_packages(&user_path)?;
+            for pkg in &mut local {
+                pkg.local = true;
+            }
+            packages.extend(local);
+        }
+        let mut registry = Registry { packages, saved: Default::default() };
+        registry.saved = registry.split()?;
+        Ok(registry)
+    }
+
+    pub fn packages(&self) -> &[InstalledPackage] {
+        &self.packages
+    }
+
+    pub fn get(&self, name: &str, local: bool) -> Option<&InstalledPackage> {
+        if local {
+            return self.get_scoped(name, true);
+        }
+        self.get_scoped(name, false).or_else(|| self.get_scoped(name, true))
+    }
+
+    pub fn get_scoped(&self, name: &str, local: bool) -> Option<&InstalledPackage> {
+        self.packages.iter().find(|p| p.name == name && p.local == local)
+    }
+
+    pub fn owner_of(&self, path: &Path, name: &str, local: bool) -> Option<&InstalledPackage> {
+        self.packages
+            .iter()
+            .filter(|p| p.name != name || p.local != local)
+            .find(|p| Path::new(&p.location) == path || p.file_paths().iter().any(|f| f == path))
+    }
+
+    pub(crate) fn upsert(&mut self, pkg: InstalledPackage) {
+        self.packages.retain(|p| p.name != pkg.name || p.local != pkg.local);
+        let claimed = pkg.file_paths();
+        for other in &mut self.packages {
+            let paths = other.file_paths();
+            let files = std::mem::take(&mut other.files);
+            other.files = files.into_iter().zip(paths).filter(|(_, path)| !claimed.contains(path)).map(|(f, _)| f).collect();
+        }
+        self.packages.push(pkg);
+    }
+
+    pub(crate) fn remove(&mut self, name: &str, local: bool) -> Option<InstalledPackage> {
+        let index = self.packages.iter().position(|p| p.name == name && p.local == local)?;
+        Some(self.packages.remove(index))
+    }
+
+    pub(crate) fn save(&self) -> io::Result<()> {
+        let (system, user) = self.split()?;
+        if system != self.saved.0 {
+            write_packages(&Self::path(), system)?;
+        }
+        if user != self.saved.1 {
+            write_packages(&Self::user_path(), user)?;
+        }
+        Ok(())
+    }
+
+    fn split(&self) -> io::Result<(String, String)> {
+        let to_yaml = |packages: Vec<&InstalledPackage>| {
+            serde_yaml::to_string(&packages).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        };
+        if Self::user_path() == Self::path() {
+            return Ok((to_yaml(self.packages.iter().collect())?, String::new()));
+        }
+        let (local, system): (Vec<&InstalledPackage>, Vec<&InstalledPackage>) = self.packages.iter().partition(|p| p.local);
+        Ok((to_yaml(system)?, if local.is_empty() && !Self::user_path().exists() { String::new() } else { to_yaml(local)? }))
+    }
+}
+
+fn read_packages(path: &Path) -> io::Result<Vec<InstalledPackage>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(path)?;
+    Ok(serde_yaml::from_str::<Option<Vec<InstalledPackage>>>(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?
+        .unwrap_or_default())
+}
+
+fn write_packages(path: &Path, content: String) -> io::Result<()> {
+    let parent = path.parent().unwrap();
+    if fs::create_dir_all(parent).is_ok() && utils::has_write_access(parent) {
+        let temp_path = path.with_extension("yaml.tmp");
+        fs::write(&temp_path, content)?;
+        return fs::rename(&temp_path, path);
+    }
+    if !parent.exists() {
+        Command::new(utils::get_privilege_command())
+            .arg("mkdir")
+            .arg("-p")
+            .arg(parent)
+            .status()?;
+    }
+    let temp_path = Path::new("/tmp").join("charoite-installed.yaml");
+    fs::write(&temp_path, content)?;
+    let status = Command::new(utils::get_privilege_command())
+        .arg("mv")
+        .arg(&temp_path)
+        .arg(path)
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other("Failed to update package list"))
+    }
+}
@@ -1,39 +1,41 @@
 use std::fs;
 use std::io;
 use std::path::Path;
-use std::process::Command;
-use ansi_term::Colour::Green;
-use serde_yaml;
-use crate::utils::{self, InstalledPackage};
+use std::process::{Command, ExitStatus};
+use std::time::Instant;
+use ansi_term::Colour::{Green, Yellow};
+use crate::batch::Batch;
+use crate::color;
+use crate::history;
+use crate::porcelain::{self, Status};
+use crate::registry::Registry;
+use crate::utils;
 
-pub fn remove_package(name: &str) -> io::Result<()> {
-    let etc_path = Path::new("/etc/charoite");
-    let installed_path = etc_path.join("installed.yaml");
-    if !installed_path.exists() {
+#[allow(clippy::io_other_error)]
+pub fn remove_package(name: &str, keep_libs: bool, local: bool) -> io::Result<()> {
+    let mut registry = Registry::load()?;
+    if registry.packages().is_empty() {
         return Err(io::Error::new(io::ErrorKind::NotFound, "No packages installed"));
     }
 
-    let content = fs::read_to_string(&installed_path)?;
-    let mut installed: Vec<InstalledPackage> = serde_yaml::from_str(&content)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-
-    if let Some(pkg) = installed.iter().find(|p| p.name == name) {
+    if let Some(pkg) = registry.get(name, local) {
+        let local = pkg.local;
         let path = Path::new(&pkg.location);
-        if !path.exists() {
+        if pkg.stow.is_none() && !path.exists() {
             return Err(io::Error::new(io::ErrorKind::NotFound, format!("File not found: {}", pkg.location)));
         }
 
         let parent = path.parent().unwrap_or_else(|| Path::new(""));
-        let system_dirs = [
-            Path::new("/usr/bin"),
-            Path::new("/usr/local/bin"),
-            Path::new("/bin"),
-            Path::new("/sbin"),
-            Path::new("/usr/sbin"),
-        ];
-        let use_sudo = system_dirs.contains(&parent);
+        let use_sudo = needs_privilege(path);
+
+        if let Some(stow_dir) = &pkg.stow {
+            remove_stow_tree(Path::new(stow_dir), parent)?;
+        }
 
-        let status = if use_sudo {
+        let status = if let Some(owner) = registry.owner_of(path, name, pkg.local) {
+            println!("~> Keeping {}, it now belongs to {}", pkg.location, owner.name);
+            Ok(ExitStatus::default())
+        } else if use_sudo {
             Command::new(utils::get_privilege_command())
                 .arg("rm")
                 .arg("-f")
@@ -48,17 +50,26 @@ pub fn remove_package(name: &str) -> io::Result<()> {
 
         if let Ok(status) = status {
             if status.success() {
-                installed.retain(|p| p.name != name);
-                let temp_path = Path::new("/tmp").join("charoite-installed.yaml");
-                let content = serde_yaml::to_string(&installed)
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                fs::write(&temp_path, content)?;
-                Command::new(utils::get_privilege_command())
-                    .arg("mv")
-                    .arg(&temp_path)
-                    .arg(&installed_path)
-                    .status()?;
-                println!("{}: Removed {}", Green.paint("Success"), name);
+                let mut removed_libs = false;
+                for file in &pkg.file_paths() {
+                    let file = file.as_path();
+                    if let Some(owner) = registry.owner_of(file, name, pkg.local) {
+                        println!("~> Keeping {}, it now belongs to {}", file.display(), owner.name);
+                        continue;
+                    }
+                    if keep_libs && is_library(file) {
+                        println!("~> Keeping {}", file.display());
+                        continue;
+                    }
+                    run_rm(&["-f"], file, needs_privilege(file))?;
+                    removed_libs |= is_shared_library(file);
+                }
+                if removed_libs && cfg!(target_os = "linux") {
+                    run_ldconfig();
+                }
+                registry.remove(name, local);
+                registry.save()?;
+                println!("{}: Removed {}", color::paint(Green, "Success"), name);
                 Ok(())
             } else {
                 Err(io::Error::new(io::ErrorKind::Other, "Failed to remove file"))
@@ -70,3 +81,87 @@ pub fn remove_package(name: &str) -> io::Result<()> {
         Err(io::Error::new(io::ErrorKind::NotFound, format!("Package {} not found", name)))
     }
 }
+
+pub fn remove_group(group: &str, keep_libs: bool, keep_going: bool) -> io::Result<()> {
+    let registry = Registry::load()?;
+    let names: Vec<(String, bool)> = registry
+        .packages()
+        .iter()
+        .filter(|p| p.group.as_deref() == Some(group))
+        .map(|p| (p.name.clone(), p.local))
+        .collect();
+    if names.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("No packages in group {}", group)));
+    }
+    let mut batch = Batch::new(keep_going);
+    for (name, local) in names {
+        let start = Instant::now();
+        let result = remove_package(&name, keep_libs, local);
+        history::record("remove", &name, local, &result, start.elapsed());
+        porcelain::report(Status::Removed, &name, &result, "");
+        batch.record(&name, result)?;
+    }
+    batch.finish()
+}
+
+fn is_shared_library(path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    name.ends_with(".so") || name.contains(".so.") || name.ends_with(".dylib")
+}
+
+fn is_library(path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    is_shared_library(path)
+        || name.ends_with(".a")
+        || name.ends_with(".pc")
+        || path.components().any(|c| c.as_os_str() == "include")
+}
+
+fn run_ldconfig() {
+    println!("~> Refreshing shared library cache");
+    let status = Command::new(utils::get_privilege_command()).arg("ldconfig").status();
+    if !status.is_ok_and(|s| s.success()) {
+        println!("{}", color::paint(Yellow, "Warning: ldconfig failed, run it manually"));
+    }
+}
+
+fn remove_stow_tree(stow_dir: &Path, bin_dir: &Path) -> io::Result<()> {
+    if let Ok(entries) = fs::read_dir(stow_dir.join("bin")) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            let link = bin_dir.join(entry.file_name());
+            if fs::read_link(&link).map(|t| t.starts_with(stow_dir)).unwrap_or(false) {
+                run_rm(&["-f"], &link, needs_privilege(&link))?;
+            }
+        }
+    }
+    if stow_dir.exists() {
+        run_rm(&["-rf"], stow_dir, needs_privilege(stow_dir))?;
+    }
+    Ok(())
+}
+
+fn needs_privilege(path: &Path) -> bool {
+    let parent = path.parent().unwrap_or_else(|| Path::new("/"));
+    let parent = fs::canonicalize(parent).unwrap_or_else(|_| parent.to_path_buf());
+    !utils::has_write_access(&parent)
+}
+
+fn run_rm(args: &[&str], path: &Path, use_sudo: bool) -> io::Result<()> {
+    let status = if use_sudo {
+        Command::new(utils::get_privilege_command())
+            .arg("rm")
+            .args(args)
+            .arg(path)
+            .status()?
+    } else {
+        Command::new("rm")
+            .args(args)
+            .arg(path)
+            .status()?
+    };
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("Failed to remove {}", path.display())))
+    }
+}
@@ -0,0 +1,61 @@
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sandbox {
+    Docker,
+    Podman,
+}
+
+impl Sandbox {
+    pub fn engine(self) -> &'static str {
+        match self {
+            Sandbox::Docker => "docker",
+            Sandbox::Podman => "podman",
+        }
+    }
+
+    pub fn wrap(self, image: &str, mounts: &[&Path], work_dir: &Path, cmd: &str, args: &[&str]) -> Vec<String> {
+        let mut wrapped = vec!["run".to_string(), "--rm".to_string()];
+        match self {
+            Sandbox::Docker => {
+                if let (Some(uid), Some(gid)) = (id("-u"), id("-g")) {
+                    wrapped.extend(["--user".to_string(), format!("{}:{}", uid, gid)]);
+                }
+            }
+            Sandbox::Podman => wrapped.push("--userns=keep-id".to_string()),
+        }
+        wrapped.extend(["-e", "HOME=/tmp", "-e", "CARGO_HOME=/tmp/.cargo"].map(String::from));
+        for mount in mounts {
+            wrapped.extend(["-v".to_string(), format!("{0}:{0}", mount.display())]);
+        }
+        wrapped.extend(["-w".to_string(), work_dir.display().to_string(), image.to_string(), cmd.to_string()]);
+        wrapped.extend(args.iter().map(|a| a.to_string()));
+        wrapped
+    }
+}
+
+fn id(flag: &str) -> Option<String> {
+    let output = Command::new("id").arg(flag).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+impl fmt::Display for Sandbox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.engine())
+    }
+}
+
+impl FromStr for Sandbox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "docker" => Ok(Sandbox::Docker),
+            "podman" => Ok(Sandbox::Podman),
+            _ => Err(format!("unknown sandbox '{}' (expected docker or podman)", s)),
+        }
+    }
+}
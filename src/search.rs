@@ -1,50 +1,173 @@
-use reqwest::blocking::Client;
-use reqwest::header;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::config::{self, Config};
+use crate::history;
+use crate::install::{self, InstallOptions, Outcome};
+use crate::utils;
 
-pub fn search(query: &str) {
-    let url = format!("https://api.github.com/search/repositories?q={}", urlencoding::encode(query));
-    let client = Client::new();
-    let response = client.get(&url)
-        .header(header::USER_AGENT, "charoite-pkg-manager")
-        .send();
-
-    let resp = match response {
-        Ok(resp) => resp,
-        Err(e) => {
-            eprintln!("Failed to access GitHub API: {}", e);
-            return;
-        }
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchResult {
+    pub name: String,
+    pub stars: u64,
+    pub forks: u64,
+    pub url: String,
+    pub source: String,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Filters {
+    pub min_stars: Option<u64>,
+    pub no_forks: bool,
+}
+
+pub fn search(query: &str, filters: Filters) -> io::Result<Vec<SearchResult>> {
+    let query = match filters.min_stars {
+        Some(stars) => format!("{} stars:>={}", query, stars),
+        None => query.to_string(),
     };
+    let url = format!("https://api.github.com/search/repositories?q={}&per_page=100", urlencoding::encode(&query));
+    let resp = utils::http_client()
+        .get(&url)
+        .send()
+        .map_err(|e| io::Error::other(format!("Failed to access GitHub API: {}", e)))?;
 
     if !resp.status().is_success() {
-        eprintln!("GitHub API error: {} - {}", resp.status(), resp.text().unwrap_or_default());
-        return;
+        return Err(io::Error::other(format!("GitHub API error: {} - {}", resp.status(), resp.text().unwrap_or_default())));
     }
 
-    let json: Value = match resp.json() {
-        Ok(v) => v,
-        Err(e) => {
-            eprintln!("Failed to parse GitHub response: {}", e);
-            return;
-        }
+    let json: Value = resp
+        .json()
+        .map_err(|e| io::Error::other(format!("Failed to parse GitHub response: {}", e)))?;
+
+    let Some(items) = json["items"].as_array() else {
+        let message = json["message"].as_str().unwrap_or("Unexpected GitHub API response format");
+        return Err(io::Error::other(format!("GitHub says: {}", message)));
     };
 
-    if let Some(items) = json["items"].as_array() {
-        println!("{:<40} {:<8} {:<8} {}", "Package", "Stars", "Forks", "Source");
-        println!("{}", "-".repeat(70));
+    Ok(items
+        .iter()
+        .filter(|item| !(filters.no_forks && item["fork"].as_bool().unwrap_or(false)))
+        .filter(|item| item["stargazers_count"].as_u64().unwrap_or(0) >= filters.min_stars.unwrap_or(0))
+        .filter_map(|item| {
+            Some(SearchResult {
+                name: item["full_name"].as_str()?.to_string(),
+                stars: item["stargazers_count"].as_u64().unwrap_or(0),
+                forks: item["forks_count"].as_u64().unwrap_or(0),
+                url: item["html_url"].as_str().unwrap_or_default().to_string(),
+                source: "GitHub".to_string(),
+            })
+        })
+        .take(10)
+        .collect())
+}
 
-        for item in items.iter().take(10) {
-            if let Some(name) = item["full_name"].as_str() {
-                let stars = item["stargazers_count"].as_u64().unwrap_or(0);
-                let forks = item["forks_count"].as_u64().unwrap_or(0);
-                println!("{:<40} {:<8} {:<8} GitHub", name, stars, forks);
-            }
-        }
-    } else {
-        eprintln!("Unexpected GitHub API response format");
-        if let Some(message) = json["message"].as_str() {
-            eprintln!("GitHub says: {}", message);
+const DEFAULT_CACHE_TTL: u64 = 600;
+
+pub fn cached_search(query: &str, filters: Filters, refresh: bool) -> io::Result<Vec<SearchResult>> {
+    let ttl = Duration::from_secs(Config::load().search_cache_ttl.unwrap_or(DEFAULT_CACHE_TTL));
+    let path = cache_file(query, filters, "github");
+    if !refresh && !ttl.is_zero() && let Some(results) = read_cache(&path, ttl) {
+        return Ok(results);
+    }
+    let results = search(query, filters)?;
+    if !ttl.is_zero() {
+        let _ = write_cache(&path, &results);
+    }
+    Ok(results)
+}
+
+fn cache_file(query: &str, filters: Filters, host: &str) -> PathBuf {
+    let key = Sha256::digest(format!("{}\n{}\n{:?}\n{}", host, query.trim(), filters.min_stars, filters.no_forks));
+    config::cache_dir().join("search").join(format!("{:x}.json", key))
+}
+
+fn read_cache(path: &Path, ttl: Duration) -> Option<Vec<SearchResult>> {
+    let age = fs::metadata(path).ok()?.modified().ok()?.elapsed().ok()?;
+    if age > ttl {
+        return None;
+    }
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn write_cache(path: &Path, results: &[SearchResult]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string(results)?)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Table,
+    Json,
+    Yaml,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "yaml" => Ok(Format::Yaml),
+            _ => Err(format!("unknown format '{}' (expected table, json or yaml)", s)),
         }
     }
 }
+
+pub fn output_results(results: &[SearchResult], format: Format) -> io::Result<()> {
+    match format {
+        Format::Table => print_results(results),
+        Format::Json => println!("{}", serde_json::to_string_pretty(results)?),
+        Format::Yaml => print!("{}", serde_yaml::to_string(results).map_err(io::Error::other)?),
+    }
+    Ok(())
+}
+
+#[allow(clippy::print_literal)]
+pub fn print_results(results: &[SearchResult]) {
+    println!("{:<4} {:<40} {:<8} {:<8} {}", "#", "Package", "Stars", "Forks", "Source");
+    println!("{}", "-".repeat(75));
+    for (i, result) in results.iter().enumerate() {
+        println!("{:<4} {:<40} {:<8} {:<8} {}", i + 1, result.name, result.stars, result.forks, result.source);
+    }
+}
+
+pub fn pick_and_install(results: &[SearchResult]) -> io::Result<()> {
+    if results.is_empty() {
+        return Ok(());
+    }
+    let choice = prompt(&format!("~> Install which package? [1-{}, empty to skip] ", results.len()))?;
+    let Some(selected) = choice.parse::<usize>().ok().and_then(|n| n.checked_sub(1)).and_then(|i| results.get(i)) else {
+        println!("No package selected");
+        return Ok(());
+    };
+    let local = prompt("~> Install locally to ~/.local/bin? [y/N] ")?.eq_ignore_ascii_case("y");
+    let opts = InstallOptions { local, ..Default::default() };
+    let start = Instant::now();
+    let result = install::install(&selected.name, &opts);
+    if !matches!(result, Ok(Outcome::Skipped)) {
+        history::record("install", &selected.name, local, &result, start.elapsed());
+    }
+    result.map(|_| ())
+}
+
+pub fn interactive_available(yes: bool) -> bool {
+    !yes && io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+fn prompt(message: &str) -> io::Result<String> {
+    print!("{}", message);
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
@@ -0,0 +1,89 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    #[default]
+    Github,
+    Gitlab,
+    Codeberg,
+    Url,
+    #[serde(rename = "crates.io")]
+    Crates,
+    Path,
+    Bundle,
+}
+
+impl Source {
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Github => "github",
+            Source::Gitlab => "gitlab",
+            Source::Codeberg => "codeberg",
+            Source::Url => "url",
+            Source::Crates => "crates.io",
+            Source::Path => "path",
+            Source::Bundle => "bundle",
+        }
+    }
+
+    pub fn domain(self) -> &'static str {
+        match self {
+            Source::Github => "github.com",
+            Source::Gitlab => "gitlab.com",
+            Source::Codeberg => "codeberg.org",
+            Source::Url | Source::Path | Source::Bundle => "",
+            Source::Crates => "crates.io",
+        }
+    }
+
+    pub fn url(self, repo: &str) -> String {
+        match self {
+            Source::Url | Source::Path | Source::Bundle => repo.to_string(),
+            Source::Crates => format!("https://crates.io/crates/{}", repo),
+            _ => format!("https://{}/{}", self.domain(), repo),
+        }
+    }
+
+    pub fn tarball_url(self, repo: &str, branch: Option<&str>) -> Option<String> {
+        match (self, branch) {
+            (Source::Github, Some(b)) => Some(format!("{}/archive/refs/heads/{}.tar.gz", self.url(repo), b)),
+            (Source::Github, None) => Some(format!("{}/archive/HEAD.tar.gz", self.url(repo))),
+            (Source::Gitlab, b) => {
+                let reference = b.unwrap_or("HEAD");
+                let name = repo.rsplit('/').next().unwrap();
+                Some(format!("{}/-/archive/{}/{}-{}.tar.gz", self.url(repo), reference, name, reference))
+            }
+            (Source::Codeberg, Some(b)) => Some(format!("{}/archive/{}.tar.gz", self.url(repo), b)),
+            (Source::Codeberg, None) => None,
+            (Source::Url | Source::Crates | Source::Path | Source::Bundle, _) => None,
+        }
+    }
+
+    pub fn allows_subgroups(self) -> bool {
+        self == Source::Gitlab
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let host = s.trim().trim_start_matches("www.").to_lowercase();
+        if host == "crate" || host == "crates" {
+            return Ok(Source::Crates);
+        }
+        [Source::Github, Source::Gitlab, Source::Codeberg, Source::Url, Source::Crates, Source::Path, Source::Bundle]
+            .into_iter()
+            .find(|source| host == source.name() || (!host.is_empty() && host == source.domain()))
+            .ok_or_else(|| format!("unknown source host '{}' (expected github, gitlab, codeberg, url, crates.io, path or bundle)", s))
+    }
+}
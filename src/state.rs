@@ -0,0 +1,80 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use ansi_term::Colour::{Green, Yellow};
+use crate::batch::Batch;
+use crate::color;
+use crate::archive;
+use crate::config;
+use crate::registry::Registry;
+use crate::update;
+use crate::utils::InstalledPackage;
+
+const REGISTRY_ENTRY: &str = "installed.yaml";
+const CONFIG_ENTRY: &str = "config.yaml";
+
+pub fn export(file: &Path) -> io::Result<()> {
+    let packages = Registry::load()?.packages().to_vec();
+    let registry = if packages.is_empty() {
+        None
+    } else {
+        Some(serde_yaml::to_string(&packages).map_err(io::Error::other)?.into_bytes())
+    };
+    let config = fs::read(config::config_path()).ok();
+    let entries: Vec<(&str, &[u8])> = [(REGISTRY_ENTRY, &registry), (CONFIG_ENTRY, &config)]
+        .into_iter()
+        .filter_map(|(name, data)| data.as_deref().map(|d| (name, d)))
+        .collect();
+    if entries.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "Nothing to export"));
+    }
+    archive::create(file, &entries)?;
+    println!("{}: Exported {} to {}",
+        color::paint(Green, "Success"),
+        entries.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", "),
+        file.display(),
+    );
+    Ok(())
+}
+
+pub fn import(file: &Path, rebuild: bool, yes: bool, prefetch: Option<usize>, keep_going: bool) -> io::Result<()> {
+    let mut imported = Vec::new();
+    for (name, data) in archive::read_entries(file)? {
+        match name.as_str() {
+            REGISTRY_ENTRY => {
+                let packages = serde_yaml::from_slice::<Option<Vec<InstalledPackage>>>(&data)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", name, e)))?
+                    .unwrap_or_default();
+                let mut registry = Registry::load()?;
+                for pkg in &packages {
+                    registry.upsert(pkg.clone());
+                }
+                registry.save()?;
+                println!("~> Restored {} package record(s)", packages.len());
+                imported = packages;
+            }
+            CONFIG_ENTRY => {
+                let path = config::config_path();
+                fs::create_dir_all(path.parent().unwrap())?;
+                fs::write(&path, data)?;
+                println!("~> Restored {}", path.display());
+            }
+            _ => println!("{}", color::paint(Yellow, format!("Skipping unknown entry {}", name))),
+        }
+    }
+
+    if rebuild {
+        let mut batch = Batch::new(keep_going);
+        let mut packages: Vec<&InstalledPackage> = imported.iter().filter(|p| p.repo.is_some()).collect();
+        if let Some(parallel) = prefetch {
+            let failed = update::prefetch_sources(&packages, parallel.max(1), &mut batch)?;
+            packages.retain(|pkg| !failed.contains(&pkg.name));
+        }
+        for pkg in packages {
+            println!("{}", color::bold(format!("~> Rebuilding {}", pkg.name)));
+            batch.record(&pkg.name, update::reinstall(pkg, yes, prefetch.is_some(), "import"))?;
+        }
+        batch.finish()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,363 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+use ansi_term::Colour::{Cyan, Green, Red, Yellow};
+use chrono::{Local, NaiveDate};
+use crate::batch::Batch;
+use crate::color;
+use crate::history;
+use crate::install::{self, InstallOptions, Outcome};
+use crate::list;
+use crate::porcelain::{self, Status};
+use crate::registry::Registry;
+use crate::source::Source;
+use crate::utils::{self, InstalledPackage};
+
+pub(crate) enum RemoteState {
+    UpToDate,
+    Outdated(String),
+    Failed(String),
+}
+
+pub fn update(name: Option<&str>, local: bool, parallel: usize, yes: bool, prefetch: bool, show_commits: bool, keep_going: bool) -> io::Result<()> {
+    let registry = Registry::load()?;
+    let packages: Vec<&InstalledPackage> = match name {
+        Some(name) => registry.get(name, local).into_iter().collect(),
+        None => registry.packages().iter().collect(),
+    };
+    if let Some(name) = name && packages.is_empty() {
+        porcelain::emit(Status::Failed, name, "not installed");
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("Package {} not found", name)));
+    }
+
+    let (packages, untracked): (Vec<&InstalledPackage>, Vec<&InstalledPackage>) =
+        packages.into_iter().partition(|p| p.repo.is_some());
+    for pkg in untracked {
+        println!("{}", color::paint(Yellow, format!("Skipping {}: no repository recorded, reinstall it to enable updates", pkg.name)));
+        porcelain::emit(Status::Skipped, &pkg.name, "no repository recorded");
+    }
+    let (pinned, packages): (Vec<&InstalledPackage>, Vec<&InstalledPackage>) =
+        packages.into_iter().partition(|p| p.pinned);
+    for pkg in pinned {
+        println!("{}", color::paint(Cyan, format!("Skipping {}: pinned, run `charoite unpin {}` to update it", pkg.name, pkg.name)));
+        porcelain::emit(Status::Skipped, &pkg.name, "pinned");
+    }
+
+    println!("{}", color::bold(format!("~> Checking {} package(s) for updates", packages.len())));
+    let states = check_remotes(&packages, parallel.max(1));
+
+    let mut outdated: Vec<(&InstalledPackage, String)> = Vec::new();
+    let mut heads = list::load_remote_heads();
+    for (pkg, state) in packages.iter().zip(states) {
+        match state {
+            RemoteState::UpToDate => {
+                println!("{}: {}", pkg.name, color::paint(Green, "up to date"));
+                porcelain::emit(Status::UpToDate, &pkg.name, &short_hash(pkg.last_commit_hash.as_deref()));
+                heads.insert(pkg.name.clone(), pkg.last_commit_hash.clone().unwrap_or_default());
+            }
+            RemoteState::Outdated(head) => {
+                println!("{}: {} -> {}", pkg.name, short_hash(pkg.last_commit_hash.as_deref()), color::paint(Yellow, short_hash(Some(&head))));
+                heads.insert(pkg.name.clone(), head.clone());
+                outdated.push((*pkg, head));
+            }
+            RemoteState::Failed(e) => {
+                eprintln!("{}: {}", pkg.name, color::paint(Red, &e));
+                porcelain::emit(Status::Failed, &pkg.name, &e);
+            }
+        }
+    }
+
+    let _ = list::save_remote_heads(&heads);
+
+    let mut batch = Batch::new(keep_going);
+    if prefetch {
+        let packages: Vec<&InstalledPackage> = outdated.iter().map(|(pkg, _)| *pkg).collect();
+        let failed = prefetch_sources(&packages, parallel.max(1), &mut batch)?;
+        outdated.retain(|(pkg, _)| !failed.contains(&pkg.name));
+    }
+    for (pkg, head) in outdated {
+        println!("{}", color::bold(format!("~> Updating {}", pkg.name)));
+        let changes_since = pkg.last_commit_hash.clone().filter(|_| show_commits);
+        let opts = InstallOptions { prefetched: prefetch, changes_since, ..reinstall_options(pkg, yes) };
+        let result = reinstall_with(pkg, &opts, "update");
+        let detail = format!("{} -> {}", short_hash(pkg.last_commit_hash.as_deref()), short_hash(Some(&head)));
+        match result {
+            Ok(Outcome::Skipped) => porcelain::emit(Status::Skipped, &pkg.name, "cancelled"),
+            _ => porcelain::report(Status::Updated, &pkg.name, &result, &detail),
+        }
+        batch.record(&pkg.name, result.map(|_| ()))?;
+    }
+    batch.finish()
+}
+
+pub fn outdated(parallel: usize) -> io::Result<()> {
+    let registry = Registry::load()?;
+    let (pinned, packages): (Vec<&InstalledPackage>, Vec<&InstalledPackage>) =
+        registry.packages().iter().filter(|p| p.repo.is_some()).partition(|p| p.pinned);
+    if packages.is_empty() && pinned.is_empty() {
+        println!("No packages installed");
+        return Ok(());
+    }
+
+    println!("{}", color::bold(format!("~> Checking {} package(s) for updates", packages.len())));
+    let states = check_remotes(&packages, parallel.max(1));
+
+    let mut rows = Vec::new();
+    let mut heads = list::load_remote_heads();
+    for (pkg, state) in packages.iter().zip(states) {
+        match state {
+            RemoteState::UpToDate => {
+                heads.insert(pkg.name.clone(), pkg.last_commit_hash.clone().unwrap_or_default());
+            }
+            RemoteState::Outdated(head) => {
+                heads.insert(pkg.name.clone(), head.clone());
+                rows.push((*pkg, head));
+            }
+            RemoteState::Failed(e) => eprintln!("{}: {}", pkg.name, color::paint(Red, e)),
+        }
+    }
+    let _ = list::save_remote_heads(&heads);
+
+    if rows.is_empty() && pinned.is_empty() {
+        println!("{}", color::paint(Green, "All packages are up to date"));
+        return Ok(());
+    }
+    println!("{:<24} {:<10} {:<10} Age", "Package", "Installed", "Latest");
+    println!("{}", "-".repeat(56));
+    for (pkg, head) in rows {
+        println!(
+            "{:<24} {:<10} {} {}",
+            pkg.name,
+            short_hash(pkg.last_commit_hash.as_deref()),
+            color::paint(Yellow, format!("{:<10}", short_hash(Some(&head)))),
+            commit_age(pkg.last_commit_date.as_deref()),
+        );
+    }
+    for pkg in pinned {
+        println!(
+            "{:<24} {:<10} {} {}",
+            pkg.name,
+            short_hash(pkg.last_commit_hash.as_deref()),
+            color::paint(Cyan, format!("{:<10}", "pinned")),
+            commit_age(pkg.last_commit_date.as_deref()),
+        );
+    }
+    Ok(())
+}
+
+pub fn set_pinned(name: &str, local: bool, pinned: bool) -> io::Result<()> {
+    let mut registry = Registry::load()?;
+    let mut pkg = registry
+        .get(name, local)
+        .cloned()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Package {} not found", name)))?;
+    if pkg.pinned == pinned {
+        println!("{} is already {}", name, if pinned { "pinned" } else { "unpinned" });
+        return Ok(());
+    }
+    pkg.pinned = pinned;
+    registry.upsert(pkg);
+    registry.save()?;
+    println!("{}", color::paint(Green, format!("~> {} {}", if pinned { "Pinned" } else { "Unpinned" }, name)));
+    Ok(())
+}
+
+fn commit_age(date: Option<&str>) -> String {
+    match date.and_then(|d| NaiveDate::parse_from_str(d, "%y-%m-%d").ok()) {
+        Some(date) => match (Local::now().date_naive() - date).num_days() {
+            0 => "today".to_string(),
+            1 => "1 day".to_string(),
+            days => format!("{} days", days),
+        },
+        None => "unknown".to_string(),
+    }
+}
+
+const SELF_REPO: &str = "unixextremist/charoite";
+
+pub fn self_update(yes: bool) -> io::Result<()> {
+    let current = env::current_exe()?.canonicalize()?;
+    let bin_dir = current.parent().ok_or_else(|| io::Error::other("Can't locate the running binary"))?;
+    let staging = PathBuf::from("/tmp/charoite/self-update");
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging)?;
+
+    println!("{}", color::bold(format!("~> Updating charoite at {}", current.display())));
+    let previous = Registry::load()?.get_scoped("charoite", false).cloned();
+    let opts = InstallOptions {
+        prefix: Some(staging.clone()),
+        yes,
+        force: true,
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let result = install::install(SELF_REPO, &opts);
+    history::record("self-update", "charoite", false, &result, start.elapsed());
+    result?;
+
+    let mut registry = Registry::load()?;
+    let staged = registry.remove("charoite", false);
+    let new_binary = staging.join("bin").join("charoite");
+    if !new_binary.exists() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "Build did not produce a charoite binary"));
+    }
+
+    let elevate = !utils::has_write_access(bin_dir);
+    let temp = bin_dir.join(".charoite.new");
+    privileged(elevate, "cp", &[&new_binary, &temp])?;
+    if let Err(e) = privileged(elevate, "mv", &["-f".as_ref(), temp.as_os_str(), current.as_os_str()]) {
+        let _ = privileged(elevate, "rm", &["-f".as_ref(), temp.as_os_str()]);
+        return Err(e);
+    }
+
+    if let Some(mut pkg) = previous {
+        if let Some(staged) = staged {
+            pkg.last_commit_hash = staged.last_commit_hash;
+            pkg.last_commit_date = staged.last_commit_date;
+            pkg.install_date = staged.install_date;
+            pkg.version = staged.version;
+            pkg.hash = staged.hash;
+        }
+        registry.upsert(pkg);
+    }
+    registry.save()?;
+    let _ = fs::remove_dir_all(&staging);
+    println!("{}", color::paint(Green, format!("~> Replaced {}", current.display())));
+    Ok(())
+}
+
+fn privileged<S: AsRef<OsStr>>(elevate: bool, cmd: &str, args: &[S]) -> io::Result<()> {
+    let mut command = if elevate {
+        let mut c = Command::new(utils::get_privilege_command());
+        c.arg(cmd);
+        c
+    } else {
+        Command::new(cmd)
+    };
+    if command.args(args).status()?.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} failed", cmd)))
+    }
+}
+
+pub fn reinstall(pkg: &InstalledPackage, yes: bool, prefetched: bool, command: &str) -> io::Result<()> {
+    reinstall_with(pkg, &InstallOptions { prefetched, ..reinstall_options(pkg, yes) }, command).map(|_| ())
+}
+
+fn reinstall_with(pkg: &InstalledPackage, opts: &InstallOptions, command: &str) -> io::Result<Outcome> {
+    let start = Instant::now();
+    let result = install::install(pkg.repo.as_deref().unwrap(), opts);
+    if !matches!(result, Ok(Outcome::Skipped)) {
+        history::record(command, &pkg.name, pkg.local, &result, start.elapsed());
+    }
+    result
+}
+
+pub fn prefetch_sources(packages: &[&InstalledPackage], parallel: usize, batch: &mut Batch) -> io::Result<Vec<String>> {
+    println!("{}", color::bold(format!("~> Prefetching sources for {} package(s)", packages.len())));
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<io::Result<()>>>> = Mutex::new(packages.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..parallel.min(packages.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(pkg) = packages.get(i) else { break };
+                let opts = InstallOptions { fetch_only: true, quiet_git: true, ..reinstall_options(pkg, true) };
+                let result = install::install(pkg.repo.as_deref().unwrap(), &opts).map(|_| ());
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    let mut failed = Vec::new();
+    for (pkg, result) in packages.iter().zip(results.into_inner().unwrap()) {
+        let result = result.unwrap();
+        if result.is_err() {
+            porcelain::report(Status::Failed, &pkg.name, &result, "");
+            failed.push(pkg.name.clone());
+        }
+        batch.record(&pkg.name, result.map_err(|e| io::Error::new(e.kind(), format!("fetching source failed: {}", e))))?;
+    }
+    Ok(failed)
+}
+
+fn reinstall_options(pkg: &InstalledPackage, yes: bool) -> InstallOptions {
+    InstallOptions {
+        local: pkg.local,
+        source: pkg.source,
+        script: pkg.build_system == "Script",
+        branch: pkg.branch.clone(),
+        stow: pkg.stow.is_some(),
+        build_cmd: pkg.build_cmd.clone(),
+        install_cmd: pkg.install_cmd.clone(),
+        extras: pkg.extras,
+        group: pkg.group.clone(),
+        mode: pkg.mode.clone(),
+        owner: pkg.owner.clone(),
+        prefix: pkg.prefix.as_ref().map(PathBuf::from),
+        target: pkg.target.clone(),
+        cmake_toolchain: pkg.cmake_toolchain.as_ref().map(PathBuf::from),
+        mirror: pkg.mirror.clone(),
+        patch_from: pkg.patch_from.clone(),
+        docdir: pkg.docdir.as_ref().map(PathBuf::from),
+        no_docs: pkg.no_docs,
+        bindir: pkg.bindir.as_ref().map(PathBuf::from),
+        libdir: pkg.libdir.as_ref().map(PathBuf::from),
+        includedir: pkg.includedir.as_ref().map(PathBuf::from),
+        flags: pkg.flags.clone(),
+        no_default_flags: pkg.no_default_flags,
+        user_flags_first: pkg.user_flags_first,
+        build_target: pkg.build_target.clone(),
+        install_target: pkg.install_target.clone(),
+        patches: pkg.patches.as_ref().map(PathBuf::from),
+        patch_urls: pkg.patch_urls.clone(),
+        rustflags: pkg.rustflags.clone(),
+        codegen_units: pkg.codegen_units,
+        rename: Some(pkg.name.clone()),
+        yes,
+        ..Default::default()
+    }
+}
+
+pub(crate) fn check_remotes(packages: &[&InstalledPackage], parallel: usize) -> Vec<RemoteState> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<RemoteState>>> = Mutex::new(packages.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..parallel.min(packages.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(pkg) = packages.get(i) else { break };
+                let state = check_remote(pkg);
+                results.lock().unwrap()[i] = Some(state);
+            });
+        }
+    });
+    results.into_inner().unwrap().into_iter().map(|s| s.unwrap()).collect()
+}
+
+fn check_remote(pkg: &InstalledPackage) -> RemoteState {
+    let source = pkg.source.unwrap_or_default();
+    let url = source.url(pkg.repo.as_deref().unwrap());
+    let head = match source {
+        Source::Url if pkg.build_system == "Script" => utils::get_remote_digest(&url),
+        Source::Crates => utils::get_crate_version(pkg.repo.as_deref().unwrap()),
+        Source::Path => utils::get_git_commit_hash(Path::new(&url)),
+        _ => utils::get_remote_head(&url, pkg.branch.as_deref()),
+    };
+    match head {
+        Ok(head) if pkg.last_commit_hash.as_deref() == Some(head.as_str()) => RemoteState::UpToDate,
+        Ok(head) => RemoteState::Outdated(head),
+        Err(e) => RemoteState::Failed(e.to_string()),
+    }
+}
+
+pub(crate) fn short_hash(hash: Option<&str>) -> String {
+    hash.map(|h| h.chars().take(7).collect()).unwrap_or_else(|| "unknown".to_string())
+}
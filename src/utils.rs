@@ -1,12 +1,23 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
 use std::io;
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use ansi_term::Colour::{Green, Red};
+use reqwest::blocking::Client;
 use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use crate::color;
+use crate::distro::PackageManager;
+use crate::source::Source;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct InstalledPackage {
     pub name: String,
-    pub source: Option<String>,
+    pub repo: Option<String>,
+    pub branch: Option<String>,
+    pub source: Option<Source>,
     pub build_system: String,
     pub location: String,
     pub build_file: Option<String>,
@@ -15,23 +26,216 @@ pub struct InstalledPackage {
     pub last_commit_hash: Option<String>,
     pub install_date: Option<String>,
     pub last_commit_date: Option<String>,
+    pub stow: Option<String>,
+    #[serde(default)]
+    pub toolchain: HashMap<String, String>,
+    pub build_cmd: Option<String>,
+    pub install_cmd: Option<String>,
+    #[serde(default)]
+    pub files: Vec<String>,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub build_duration: Option<u64>,
+    #[serde(default)]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub cmake_toolchain: Option<String>,
+    #[serde(default)]
+    pub binary_hash: Option<String>,
+    #[serde(default)]
+    pub mirror: Option<String>,
+    #[serde(default)]
+    pub cloned_from: Option<String>,
+    #[serde(default)]
+    pub local: bool,
+    #[serde(default)]
+    pub dirty: bool,
+    #[serde(default)]
+    pub patch_from: Vec<String>,
+    #[serde(default)]
+    pub docdir: Option<String>,
+    #[serde(default)]
+    pub no_docs: bool,
+    #[serde(default)]
+    pub bindir: Option<String>,
+    #[serde(default)]
+    pub libdir: Option<String>,
+    #[serde(default)]
+    pub includedir: Option<String>,    #[serde(default)]
+    pub extras: bool,
+    #[serde(default)]
+    pub flags: Vec<String>,
+    #[serde(default)]
+    pub no_default_flags: bool,
+    #[serde(default)]
+    pub user_flags_first: bool,
+    #[serde(default)]
+    pub build_target: Option<String>,
+    #[serde(default)]
+    pub install_target: Option<String>,
+    #[serde(default)]
+    pub patches: Option<String>,
+    #[serde(default)]
+    pub patch_urls: Vec<String>,
+    #[serde(default)]
+    pub rustflags: Option<String>,
+    #[serde(default)]
+    pub codegen_units: Option<usize>,
 }
 
-pub fn check_deps(deps: &[String]) {
-    for dep in deps {
-        if !check_dependency(dep) {
-            eprintln!("Dependency not found: {}", dep);
-            std::process::exit(1);
+impl InstalledPackage {
+    pub fn file_paths(&self) -> Vec<PathBuf> {
+        let prefix = install_prefix(self.local, self.prefix.as_deref().map(Path::new));
+        self.files.iter().map(|f| prefix.join(f)).collect()
+    }
+}
+
+pub fn install_prefix(local: bool, prefix: Option<&Path>) -> PathBuf {
+    match prefix {
+        Some(prefix) => prefix.to_path_buf(),
+        None if local => PathBuf::from(env::var("HOME").unwrap_or_default()).join(".local"),
+        None => PathBuf::from("/usr/local"),
+    }
+}
+
+pub fn relative_paths(files: &[PathBuf], prefix: &Path) -> Vec<String> {
+    files.iter().map(|f| f.strip_prefix(prefix).unwrap_or(f).to_string_lossy().to_string()).collect()
+}
+
+pub fn find_missing_deps(deps: &[String]) -> Vec<String> {
+    deps.iter().filter(|dep| !check_dependency(split_dep(dep).0)).cloned().collect()
+}
+
+pub fn split_dep(dep: &str) -> (&str, Option<&str>) {
+    match dep.split_once(">=") {
+        Some((name, min)) => (name.trim(), Some(min.trim())),
+        None => (dep.trim(), None),
+    }
+}
+
+pub fn parse_version(text: &str) -> Option<Vec<u64>> {
+    text.split_whitespace().find_map(|word| {
+        let word = word.trim_start_matches(|c: char| c.is_ascii_alphabetic() || c == '"');
+        let end = word.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(word.len());
+        let version = word[..end].trim_end_matches('.');
+        if !version.starts_with(|c: char| c.is_ascii_digit()) || !version.contains('.') {
+            return None;
+        }
+        version.split('.').map(|part| part.parse().ok()).collect()
+    })
+}
+
+pub fn dep_version(name: &str) -> Option<Vec<u64>> {
+    let args: &[&str] = match name {
+        "go" => &["version"],
+        "java" => &["-version"],
+        _ => &["--version"],
+    };
+    if let Ok(output) = Command::new(name).args(args).output() {
+        let text = format!("{}\n{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        if let Some(version) = text.lines().find_map(parse_version) {
+            return Some(version);
+        }
+    }
+    let output = Command::new("pkg-config")
+        .arg("--modversion")
+        .arg(name)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+pub fn version_at_least(found: &[u64], min: &[u64]) -> bool {
+    let len = found.len().max(min.len());
+    let pad = |v: &[u64]| (0..len).map(|i| v.get(i).copied().unwrap_or(0)).collect::<Vec<_>>();
+    pad(found) >= pad(min)
+}
+
+pub fn find_outdated_deps(deps: &[String]) -> Vec<String> {
+    deps.iter()
+        .filter_map(|dep| {
+            let (name, min) = split_dep(dep);
+            let min = min?;
+            let Some(required) = min.split('.').map(|part| part.parse().ok()).collect::<Option<Vec<u64>>>() else {
+                return Some(format!("{} (invalid version constraint '{}')", name, min));
+            };
+            match dep_version(name) {
+                Some(found) if version_at_least(&found, &required) => None,
+                Some(found) => Some(format!("{} {} (need >= {})", name, join_version(&found), min)),
+                None => Some(format!("{} unknown version (need >= {})", name, min)),
+            }
+        })
+        .collect()
+}
+
+fn join_version(version: &[u64]) -> String {
+    version.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(".")
+}
+
+pub fn report_check(label: &str, ok: bool) -> usize {
+    if ok {
+        println!("{} {}", color::paint(Green, "[ok]"), label);
+        0
+    } else {
+        println!("{} {}", color::paint(Red, "[!!]"), label);
+        1
+    }
+}
+
+pub fn check_deps(deps: &[String], verify_versions: bool) -> io::Result<()> {
+    if !deps.is_empty() {
+        println!("~> Checking dependencies");
+    }
+    let missing: Vec<String> = deps
+        .iter()
+        .filter(|dep| report_check(dep, check_dependency(split_dep(dep).0)) > 0)
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        if let Some(manager) = PackageManager::detect() {
+            let packages: Vec<String> = missing.iter().map(|d| manager.package_name(split_dep(d).0)).collect();
+            eprintln!("try: {}", manager.command(&packages));
         }
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Missing dependencies: {}", missing.join(", ")),
+        ));
+    }
+    if !verify_versions {
+        return Ok(());
+    }
+    let outdated = find_outdated_deps(deps);
+    if outdated.is_empty() {
+        return Ok(());
+    }
+    for dep in &outdated {
+        eprintln!("Dependency too old: {}", dep);
     }
+    Err(io::Error::other(format!("Outdated dependencies: {}", outdated.join(", "))))
 }
 
+#[allow(clippy::collapsible_if)]
 pub fn check_dependency(dep: &str) -> bool {
     if dep == "pkg-config" {
         return check_pkg_config();
     }
     let status = Command::new("which")
         .arg(dep)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
         .status()
         .map(|s| s.success())
         .unwrap_or(false);
@@ -51,11 +255,63 @@ pub fn check_dependency(dep: &str) -> bool {
 fn check_pkg_config() -> bool {
     Command::new("pkg-config")
         .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
         .status()
         .map(|s| s.success())
         .unwrap_or(false)
 }
 
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    Ok(format!("{:x}", Sha256::digest(fs::read(path)?)))
+}
+
+pub fn get_remote_digest(url: &str) -> io::Result<String> {
+    let resp = http_client().get(url).send().map_err(io::Error::other)?;
+    if !resp.status().is_success() {
+        return Err(io::Error::other(format!("Failed to query {}: {}", url, resp.status())));
+    }
+    let content = resp.bytes().map_err(io::Error::other)?;
+    Ok(format!("{:x}", Sha256::digest(&content)))
+}
+
+pub fn get_crate_version(name: &str) -> io::Result<String> {
+    let url = format!("https://crates.io/api/v1/crates/{}", name);
+    let resp = http_client().get(&url).send().map_err(io::Error::other)?;
+    if !resp.status().is_success() {
+        return Err(io::Error::other(format!("Failed to query {}: {}", url, resp.status())));
+    }
+    let body: serde_json::Value = resp.json().map_err(io::Error::other)?;
+    body["crate"]["max_stable_version"]
+        .as_str()
+        .or_else(|| body["crate"]["max_version"].as_str())
+        .map(String::from)
+        .ok_or_else(|| io::Error::other(format!("No published version of {} on crates.io", name)))
+}
+
+pub fn http_client() -> Client {
+    Client::builder()
+        .user_agent("charoite-pkg-manager")
+        .build()
+        .expect("Failed to build HTTP client")
+}
+
+pub fn has_write_access(dir: &Path) -> bool {
+    Command::new("test").arg("-w").arg(dir).status().is_ok_and(|s| s.success())
+}
+
+pub fn is_bsd() -> bool {
+    cfg!(any(target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", target_os = "dragonfly"))
+}
+
+pub fn make_program(dir: &Path) -> &'static str {
+    if is_bsd() && !dir.join("BSDMakefile").exists() && check_dependency("gmake") {
+        "gmake"
+    } else {
+        "make"
+    }
+}
+
 pub fn get_privilege_command() -> String {
     if Path::new("/usr/bin/doas").exists() {
         "doas".to_string()
@@ -64,6 +320,60 @@ pub fn get_privilege_command() -> String {
     }
 }
 
+pub fn tool_version(tool: &str) -> Option<String> {
+    let output = Command::new(tool)
+        .arg("--version")
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+}
+
+pub fn collect_toolchain(tools: &[&str]) -> HashMap<String, String> {
+    tools
+        .iter()
+        .filter_map(|tool| tool_version(tool).map(|v| (tool.to_string(), v)))
+        .collect()
+}
+
+pub fn remote_exists(url: &str) -> bool {
+    Command::new("git")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .arg("ls-remote")
+        .arg(url)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+pub fn get_remote_head(url: &str, branch: Option<&str>) -> io::Result<String> {
+    let output = Command::new("git")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .arg("ls-remote")
+        .arg(url)
+        .arg(branch.unwrap_or("HEAD"))
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("Failed to query {}", url)));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .map(|h| h.to_string())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No such ref on {}", url)))
+}
+
+#[allow(clippy::io_other_error)]
 pub fn get_git_commit_hash(path: &Path) -> io::Result<String> {
     let output = Command::new("git")
         .arg("rev-parse")
@@ -77,6 +387,7 @@ pub fn get_git_commit_hash(path: &Path) -> io::Result<String> {
     }
 }
 
+#[allow(clippy::io_other_error)]
 pub fn get_git_commit_date(path: &Path) -> io::Result<String> {
     let output = Command::new("git")
         .arg("log")
@@ -91,3 +402,41 @@ pub fn get_git_commit_date(path: &Path) -> io::Result<String> {
         Err(io::Error::new(io::ErrorKind::Other, "Failed to get commit date"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_version_reads_tool_output() {
+        assert_eq!(parse_version("cmake version 3.20.1"), Some(vec![3, 20, 1]));
+        assert_eq!(parse_version("meson 1.2.3-rc1"), Some(vec![1, 2, 3]));
+        assert_eq!(parse_version("go version go1.22.4 linux/amd64"), Some(vec![1, 22, 4]));
+        assert_eq!(parse_version("openjdk version \"17.0.2\" 2022-01-18"), Some(vec![17, 0, 2]));
+        assert_eq!(parse_version("tool v2.5."), Some(vec![2, 5]));
+    }
+
+    #[test]
+    fn parse_version_needs_a_dotted_number() {
+        assert_eq!(parse_version("v2"), None);
+        assert_eq!(parse_version("no version here"), None);
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn version_at_least_pads_missing_components() {
+        assert!(version_at_least(&[3, 20], &[3, 20, 0]));
+        assert!(version_at_least(&[3, 20, 1], &[3, 20]));
+        assert!(version_at_least(&[4], &[3, 20]));
+        assert!(!version_at_least(&[3, 19, 9], &[3, 20]));
+        assert!(!version_at_least(&[1, 2, 3], &[1, 2, 3, 1]));
+    }
+
+    #[test]
+    fn split_dep_separates_minimum_version() {
+        assert_eq!(split_dep("cmake>=3.20"), ("cmake", Some("3.20")));
+        assert_eq!(split_dep(" cmake >= 3.20 "), ("cmake", Some("3.20")));
+        assert_eq!(split_dep("cmake>="), ("cmake", Some("")));
+        assert_eq!(split_dep("pkg-config"), ("pkg-config", None));
+    }
+}
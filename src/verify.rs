@@ -0,0 +1,99 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use ansi_term::Colour::{Green, Red, Yellow};
+use crate::color;
+use crate::install::{self, InstallOptions};
+use crate::registry::Registry;
+use crate::source::Source;
+use crate::update;
+use crate::utils;
+
+pub fn verify(name: &str, local: bool) -> io::Result<()> {
+    let registry = Registry::load()?;
+    let pkg = registry
+        .get(name, local)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Package {} not found", name)))?;
+    let (Some(repo), Some(commit)) = (&pkg.repo, &pkg.last_commit_hash) else {
+        return Err(io::Error::other(format!("{} has no recorded repository and commit, reinstall it first", name)));
+    };
+    let Some(recorded) = &pkg.binary_hash else {
+        return Err(io::Error::other(format!("{} has no recorded binary hash, reinstall it first", name)));
+    };
+    if matches!(pkg.build_system.as_str(), "Ninja" | "Pip") {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Can't verify {} builds, they install outside the staging prefix", pkg.build_system),
+        ));
+    }
+
+    println!("{}", color::bold(format!("~> Verifying {} at {}", name, update::short_hash(Some(commit)))));
+    let installed = utils::file_sha256(Path::new(&pkg.location))?;
+    if &installed != recorded {
+        println!("{}", color::paint(Yellow, format!("Warning: {} changed since it was installed", pkg.location)));
+    }
+
+    let staging = PathBuf::from("/tmp/charoite/verify").join(name);
+    let _ = fs::remove_dir_all(&staging);
+    let opts = InstallOptions {
+        source: pkg.source,
+        branch: pkg.branch.clone(),
+        commit: Some(commit.clone()),
+        patch_from: pkg.patch_from.clone(),
+        docdir: pkg.docdir.as_ref().map(PathBuf::from),
+        no_docs: pkg.no_docs,
+        bindir: pkg.bindir.as_ref().map(PathBuf::from),
+        libdir: pkg.libdir.as_ref().map(PathBuf::from),
+        includedir: pkg.includedir.as_ref().map(PathBuf::from),
+        build_cmd: pkg.build_cmd.clone(),
+        install_cmd: pkg.install_cmd.clone(),
+        target: pkg.target.clone(),
+        cmake_toolchain: pkg.cmake_toolchain.as_ref().map(PathBuf::from),
+        flags: pkg.flags.clone(),
+        no_default_flags: pkg.no_default_flags,
+        user_flags_first: pkg.user_flags_first,
+        build_target: pkg.build_target.clone(),
+        install_target: pkg.install_target.clone(),
+        patches: pkg.patches.as_ref().map(PathBuf::from),
+        patch_urls: pkg.patch_urls.clone(),
+        rustflags: pkg.rustflags.clone(),
+        codegen_units: pkg.codegen_units,
+        prefix: Some(staging.clone()),
+        rename: Some(name.to_string()),
+        allow_downgrade: true,
+        no_record: true,
+        yes: true,
+        ..Default::default()
+    };
+    let spec = match pkg.source {
+        Some(Source::Crates) => format!("{}@{}", repo, commit),
+        _ => repo.clone(),
+    };
+    let result = install::install(&spec, &opts);
+    let fresh = result.and_then(|_| utils::file_sha256(&staging.join("bin").join(name)));
+    let _ = fs::remove_dir_all(&staging);
+    let fresh = fresh?;
+
+    println!("installed:   {}", installed);
+    println!("recorded:    {}", recorded);
+    println!("fresh build: {}", fresh);
+    let current = utils::collect_toolchain(&pkg.toolchain.keys().map(|k| k.as_str()).collect::<Vec<_>>());
+    for (tool, version) in &pkg.toolchain {
+        if current.get(tool) != Some(version) {
+            println!("{}", color::paint(Yellow, format!(
+                "toolchain changed: {} {} -> {}",
+                tool,
+                version,
+                current.get(tool).map(|v| v.as_str()).unwrap_or("missing"),
+            )));
+        }
+    }
+    println!("note: builds only match bit for bit when the project and toolchain are deterministic; timestamps, build paths or a different compiler version also cause a mismatch");
+    if fresh == installed {
+        println!("{}", color::paint(Green, "~> MATCH: the installed binary is identical to a fresh build"));
+        Ok(())
+    } else {
+        println!("{}", color::paint(Red, "~> MISMATCH: the installed binary differs from a fresh build"));
+        Err(io::Error::other(format!("{} does not match a fresh build of {}", name, update::short_hash(Some(commit)))))
+    }
+}